@@ -0,0 +1,88 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+use shared::filter::{Rule, SongFilter};
+
+static BLOCKLIST: Mutex<Blocklist> = Mutex::new(Blocklist::new());
+
+pub fn get() -> MutexGuard<'static, Blocklist> {
+    BLOCKLIST.lock().unwrap()
+}
+
+/// The songs the host does or does not want to be played. Rules are read from the blocklist and
+/// allowlist files, and songs blocked from the UI are appended to the blocklist file.
+pub struct Blocklist {
+    filter: SongFilter,
+    path: Option<PathBuf>,
+}
+
+impl Blocklist {
+    const fn new() -> Self {
+        Self {
+            filter: SongFilter {
+                blocked: Vec::new(),
+                allowed: Vec::new(),
+            },
+            path: None,
+        }
+    }
+
+    pub fn load(&mut self, blocklist: Option<PathBuf>, allowlist: Option<PathBuf>) -> Result<()> {
+        // the blocklist is created when the first song is blocked from the ui
+        if let Some(ref path) = blocklist
+            && path.exists()
+        {
+            self.filter.blocked = read_rules(path)?;
+        }
+        if let Some(ref path) = allowlist {
+            self.filter.allowed = read_rules(path)?;
+        }
+        self.path = blocklist;
+        Ok(())
+    }
+
+    pub fn filter(&self) -> &SongFilter {
+        &self.filter
+    }
+
+    pub fn block_id(&mut self, id: &str) {
+        if self.filter.blocks_id(id) {
+            return;
+        }
+
+        let rule = Rule::Id(id.to_owned());
+        if let Some(ref path) = self.path
+            && let Err(e) = append_rule(path, &rule)
+        {
+            log::error!("failed to add {id} to blocklist: {e}");
+        }
+        self.filter.blocked.push(rule);
+    }
+}
+
+fn read_rules(path: &Path) -> Result<Vec<Rule>> {
+    let data =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let rules = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match line.parse() {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                log::warn!("skipping line in {}: {e}", path.display());
+                None
+            }
+        })
+        .collect();
+    Ok(rules)
+}
+
+fn append_rule(path: &Path, rule: &Rule) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{rule}")?;
+    Ok(())
+}
@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use shared::protocol;
+
+use crate::util;
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache::new());
+
+pub fn get() -> MutexGuard<'static, Cache> {
+    CACHE.lock().unwrap()
+}
+
+/// Returns whether the song is cached and its audio file is playable. Files are only probed if
+/// they have not been probed when they were added to the cache. A corrupt file is deleted, so that
+/// the song is downloaded again.
+pub fn check(id: &str) -> bool {
+    let path = util::audio_cache_location(id);
+    match get().entries.get(id) {
+        None => return false,
+        Some(entry) if entry.duration.is_some() => return path.exists(),
+        Some(_) => {}
+    }
+
+    // probe without holding the lock, it takes a while
+    match probe_duration(&path) {
+        Ok(duration) => {
+            get().set_duration(id, duration);
+            true
+        }
+        Err(e) => {
+            log::warn!("cached audio of {id} is corrupt, deleting it: {e}");
+            get().discard(id);
+            false
+        }
+    }
+}
+
+/// Returns the duration of an audio file in seconds. Fails if the file cannot be decoded or has no
+/// duration, e.g. because its download was interrupted.
+pub fn probe_duration(path: &Path) -> Result<f64> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let duration = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()?;
+    if duration <= 0.0 {
+        bail!("invalid duration {duration}");
+    }
+    Ok(duration)
+}
+
+/// Index of the downloaded audio files. Keeps the size of the cache below the budget by evicting
+/// the songs that were played least recently.
+pub struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    /// Maximum size of the cached audio files in bytes. 0 disables the limit.
+    budget: u64,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CacheEntry {
+    /// Size of the audio file in bytes.
+    pub size: u64,
+    /// Time at which the song was downloaded as a UNIX timestamp in seconds.
+    pub added_at: u64,
+    /// Time at which the song was last played as a UNIX timestamp in seconds.
+    pub last_played: Option<u64>,
+    /// Duration of the audio in seconds, unset if the file has not been probed yet.
+    #[serde(default)]
+    pub duration: Option<f64>,
+}
+
+/// Why [`Cache::verify`] removed an entry from the index.
+pub enum Removal {
+    /// The audio file of the entry no longer exists.
+    Missing,
+    /// The audio file could not be read and was deleted.
+    Corrupt,
+}
+
+impl CacheEntry {
+    /// Time of the last use of the song, which decides the order of eviction.
+    fn last_used(&self) -> u64 {
+        self.last_played.unwrap_or(self.added_at)
+    }
+}
+
+impl Cache {
+    const fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget: 0,
+        }
+    }
+
+    /// Loads the index. If there is none yet, it is built from the files in the cache directory.
+    pub fn load(&mut self, budget_mb: u64) -> Result<()> {
+        self.budget = budget_mb * 1024 * 1024;
+
+        let path = util::cache_index_location();
+        match fs::read(&path) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(entries) => self.entries = entries,
+                Err(e) => {
+                    log::warn!("rebuilding cache index, {} is invalid: {e}", path.display());
+                    self.rebuild()?;
+                }
+            },
+            Err(_) => self.rebuild()?,
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &CacheEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+
+    /// Adds a downloaded song whose audio was probed to have the given duration, then evicts songs
+    /// until the cache fits the budget again. Songs in `in_use` are never evicted.
+    pub fn insert(&mut self, id: &str, duration: f64, in_use: &[String]) {
+        let size = match fs::metadata(util::audio_cache_location(id)) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                log::error!("failed to add {id} to cache index: {e}");
+                return;
+            }
+        };
+        self.entries.insert(
+            id.to_owned(),
+            CacheEntry {
+                size,
+                added_at: now(),
+                last_played: None,
+                duration: Some(duration),
+            },
+        );
+        self.prune(in_use);
+    }
+
+    fn set_duration(&mut self, id: &str, duration: f64) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.duration = Some(duration);
+            self.save();
+        }
+    }
+
+    pub fn touch(&mut self, id: &str) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.last_played = Some(now());
+            self.save();
+        }
+    }
+
+    /// Evicts the least recently used songs until the cache fits the budget. Returns the IDs of
+    /// the evicted songs.
+    pub fn prune(&mut self, in_use: &[String]) -> Vec<String> {
+        let evicted = self.songs_to_evict(in_use);
+        for id in &evicted {
+            self.remove(id);
+        }
+        if self.budget > 0 && self.total_size() > self.budget {
+            log::warn!("cache exceeds its budget, all remaining songs are in use");
+        }
+
+        self.save();
+        evicted
+    }
+
+    /// Returns the IDs of the least recently used songs that have to be evicted to fit the budget.
+    fn songs_to_evict(&self, in_use: &[String]) -> Vec<String> {
+        if self.budget == 0 {
+            return Vec::new();
+        }
+
+        let mut candidates = self
+            .entries
+            .iter()
+            .filter(|(id, _)| !in_use.contains(*id))
+            .map(|(id, entry)| (entry.last_used(), id.clone()))
+            .collect::<Vec<_>>();
+        candidates.sort();
+
+        let mut evicted = Vec::new();
+        let mut total = self.total_size();
+        for (_, id) in candidates {
+            if total <= self.budget {
+                break;
+            }
+            total -= self.entries[&id].size;
+            evicted.push(id);
+        }
+        evicted
+    }
+
+    /// Checks the index against the cache directory. Entries whose file is missing are removed,
+    /// files missing from the index are added and corrupt files are deleted. Returns the IDs of
+    /// the removed entries and why they were removed.
+    pub fn verify(&mut self) -> Result<Vec<(String, Removal)>> {
+        let missing = self
+            .entries
+            .keys()
+            .filter(|id| !util::audio_cache_location(id).exists())
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in &missing {
+            self.entries.remove(id);
+        }
+
+        for (id, size) in audio_files()? {
+            let entry = self.entries.entry(id).or_insert(CacheEntry {
+                size,
+                added_at: now(),
+                last_played: None,
+                duration: None,
+            });
+            entry.size = size;
+        }
+
+        let mut removed = missing
+            .into_iter()
+            .map(|id| (id, Removal::Missing))
+            .collect::<Vec<_>>();
+        let ids = self.entries.keys().cloned().collect::<Vec<_>>();
+        for id in ids {
+            match probe_duration(&util::audio_cache_location(&id)) {
+                Ok(duration) => self.entries.get_mut(&id).unwrap().duration = Some(duration),
+                Err(e) => {
+                    log::warn!("cached audio of {id} is corrupt: {e}");
+                    self.discard(&id);
+                    removed.push((id, Removal::Corrupt));
+                }
+            }
+        }
+
+        self.save();
+        Ok(removed)
+    }
+
+    /// Deletes all cached songs, including song info without audio.
+    pub fn clear(&mut self) -> Result<()> {
+        for entry in fs::read_dir(cache_dir())? {
+            let path = entry?.path();
+            let is_song = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(protocol::is_valid_song_id);
+            if is_song {
+                fs::remove_file(&path)?;
+            }
+        }
+        self.entries.clear();
+        self.save();
+        Ok(())
+    }
+
+    /// Deletes the audio of a song whose file is corrupt. Its song info is kept.
+    pub fn discard(&mut self, id: &str) {
+        self.entries.remove(id);
+        let path = util::audio_cache_location(id);
+        if let Err(e) = fs::remove_file(&path) {
+            log::warn!("failed to remove {}: {e}", path.display());
+        }
+        self.save();
+    }
+
+    fn remove(&mut self, id: &str) {
+        log::info!("evicting {id} from cache");
+        self.entries.remove(id);
+        for path in [
+            util::audio_cache_location(id),
+            util::song_info_cache_location(id),
+        ] {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("failed to remove {}: {e}", path.display());
+            }
+        }
+    }
+
+    fn rebuild(&mut self) -> Result<()> {
+        log::info!("building cache index");
+        self.entries = audio_files()?
+            .into_iter()
+            .map(|(id, size)| {
+                let entry = CacheEntry {
+                    size,
+                    added_at: now(),
+                    last_played: None,
+                    duration: None,
+                };
+                (id, entry)
+            })
+            .collect();
+        self.save();
+        Ok(())
+    }
+
+    fn save(&self) {
+        let path = util::cache_index_location();
+        let tmp_path = path.with_extension("json.tmp");
+        let result = fs::create_dir_all(cache_dir())
+            .and_then(|()| fs::write(&tmp_path, serde_json::to_vec(&self.entries)?))
+            .and_then(|()| fs::rename(&tmp_path, &path));
+        if let Err(e) = result {
+            log::error!("failed to write cache index: {e}");
+        }
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    util::cache_index_location().parent().unwrap().to_owned()
+}
+
+/// Returns the IDs and sizes of all audio files in the cache directory.
+fn audio_files() -> Result<Vec<(String, u64)>> {
+    let dir = cache_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("m4a") {
+            continue;
+        }
+        if let Some(id) = path.file_stem().and_then(|stem| stem.to_str())
+            && protocol::is_valid_song_id(id)
+        {
+            files.push((id.to_owned(), entry.metadata()?.len()));
+        }
+    }
+    Ok(files)
+}
+
+/// Deletes the files of downloads that were interrupted, e.g. because the client crashed.
+pub fn remove_partial_downloads() -> Result<()> {
+    let dir = cache_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if name.contains(".tmp.m4a") {
+            log::info!("removing partial download {}", path.display());
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(budget: u64, entries: &[(&str, u64, u64, Option<u64>)]) -> Cache {
+        let entries = entries
+            .iter()
+            .map(|&(id, size, added_at, last_played)| {
+                let entry = CacheEntry {
+                    size,
+                    added_at,
+                    last_played,
+                    duration: None,
+                };
+                (id.to_owned(), entry)
+            })
+            .collect();
+        Cache { entries, budget }
+    }
+
+    #[test]
+    fn nothing_is_evicted_within_budget() {
+        let cache = cache(30, &[("a", 10, 1, None), ("b", 20, 2, None)]);
+        assert_eq!(cache.total_size(), 30);
+        assert!(cache.songs_to_evict(&[]).is_empty());
+    }
+
+    #[test]
+    fn least_recently_used_songs_are_evicted_first() {
+        let cache = cache(
+            25,
+            &[
+                ("a", 10, 1, Some(5)),
+                ("b", 10, 2, None),
+                ("c", 10, 3, Some(4)),
+                ("d", 10, 6, None),
+            ],
+        );
+        assert_eq!(cache.songs_to_evict(&[]), ["b", "c"]);
+    }
+
+    #[test]
+    fn songs_in_use_are_kept() {
+        let cache = cache(
+            15,
+            &[("a", 10, 1, None), ("b", 10, 2, None), ("c", 10, 3, None)],
+        );
+        assert_eq!(cache.songs_to_evict(&["a".to_owned()]), ["b", "c"]);
+        let in_use = ["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        assert!(cache.songs_to_evict(&in_use).is_empty());
+    }
+
+    #[test]
+    fn zero_budget_disables_eviction() {
+        let cache = cache(0, &[("a", 10, 1, None), ("b", 10, 2, None)]);
+        assert!(cache.songs_to_evict(&[]).is_empty());
+    }
+}
@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::fallback::{PlaybackMode, PlaylistSpec};
+use crate::history::ExportFormat;
 
 #[derive(Parser)]
 pub struct Cli {
@@ -16,8 +19,134 @@ pub struct Cli {
     #[arg(long, short = 'P', default_value_t = shared::consts::SERVER_PORT_PUBLIC)]
     pub server_port: u16,
 
-    /// Path to the fallback playlist file. This file should contain one YouTube
-    /// video ID per line.
+    /// Path to a fallback playlist file: plain text with one song per line, M3U,
+    /// PLS or JSON. A song is a YouTube video ID, video URL or playlist URL, a path
+    /// to a local audio file or directory or an HTTP URL of an audio file. A local
+    /// audio file or directory can also be given directly.
+    ///
+    /// May be given several times, optionally followed by
+    /// `,name=<NAME>,weight=<WEIGHT>,time=<HH:MM>-<HH:MM>`. Fallback songs are
+    /// chosen by weight from the playlists whose time window contains the current
+    /// time.
     #[arg(long, short = 'f')]
-    pub fallback_playlist: Option<PathBuf>,
+    pub fallback_playlist: Vec<PlaylistSpec>,
+
+    /// Order in which the songs of the fallback playlist are played. The
+    /// fallback playlist starts over when all of its songs were played.
+    #[arg(long, value_enum, default_value_t = PlaybackMode::Loop)]
+    pub fallback_mode: PlaybackMode,
+
+    /// Number of hours in which a fallback song is not played again with
+    /// `--fallback-mode no-repeat`
+    #[arg(long, default_value_t = 4)]
+    pub no_repeat_hours: u64,
+
+    /// Number of listener votes needed to move a queued song up by one
+    /// position. 0 disables upvoting.
+    #[arg(long, default_value_t = 3)]
+    pub upvote_threshold: u32,
+
+    /// Number of listener votes needed to skip the current song. 0 disables
+    /// skipping.
+    #[arg(long, default_value_t = 5)]
+    pub skip_threshold: u32,
+
+    /// Maximum number of songs a single listener may have in the queue. 0
+    /// disables the limit.
+    #[arg(long, default_value_t = 3)]
+    pub max_songs_per_guest: u32,
+
+    /// Hide songs marked as explicit from the search results on the
+    /// submission page
+    #[arg(long)]
+    pub hide_explicit: bool,
+
+    /// Maximum duration of submitted songs in seconds. 0 disables the
+    /// limit.
+    #[arg(long, default_value_t = 15 * 60)]
+    pub max_duration: u64,
+
+    /// Path to a file with songs that may not be played. Songs blocked from
+    /// the user interface are added to this file.
+    #[arg(long, short = 'b')]
+    pub blocklist: Option<PathBuf>,
+
+    /// Path to a file with songs that may be played. If given, all other
+    /// songs are rejected.
+    #[arg(long, short = 'a')]
+    pub allowlist: Option<PathBuf>,
+
+    /// Require song suggestions to be approved in the user interface before
+    /// they are queued
+    #[arg(long, short = 'm')]
+    pub moderate: bool,
+
+    /// Start with empty queues instead of restoring the queues and the
+    /// playing song of the previous run
+    #[arg(long)]
+    pub no_resume: bool,
+
+    /// Directory with a copy of the music library searched by the server when
+    /// it uses the `library` search backend. Songs of the library are played
+    /// from this directory.
+    #[arg(long, short = 'l')]
+    pub library: Option<PathBuf>,
+
+    /// Maximum size of the downloaded audio files in megabytes. The songs
+    /// that were played least recently are deleted first. 0 disables the
+    /// limit.
+    #[arg(long, global = true, default_value_t = 10 * 1024)]
+    pub cache_size: u64,
+
+    /// Number of songs that are downloaded at the same time
+    #[arg(long, default_value_t = 3)]
+    pub download_workers: usize,
+
+    /// Number of fallback songs that are downloaded ahead of playback. Song
+    /// suggestions are always downloaded first.
+    #[arg(long, default_value_t = 3)]
+    pub fallback_prefetch: usize,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage the history of played songs
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Manage the downloaded songs
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// List the downloaded songs, least recently played first
+    List,
+    /// Check the cache index against the downloaded files
+    Verify,
+    /// Delete the least recently played songs until the cache fits its size
+    Prune,
+    /// Delete all downloaded songs
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// Export the history of played songs
+    Export {
+        /// Format of the export
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// File to write the export to. Defaults to standard output.
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
 }
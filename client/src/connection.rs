@@ -1,14 +1,20 @@
 use std::io::ErrorKind;
 use std::net::TcpStream;
-use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use shared::filter::SongFilter;
+use shared::protocol::{
+    self, ClientMessage, PlayerStatus, ServerMessage, SessionConfig, SongStatus, PROTOCOL_VERSION,
+};
 use tungstenite::stream::MaybeTlsStream;
 use tungstenite::{Error, Message, WebSocket};
 
+use crate::source::SourceRef;
 use crate::util::{self, Event};
+use crate::{blocklist, history, state};
 
 pub struct Connection {
     msg_tx: Sender<ThreadMessage>,
@@ -19,6 +25,8 @@ impl Connection {
     pub fn start(
         event_tx: Sender<Event>,
         request_id: Option<String>,
+        resume_token: Option<String>,
+        session_config: SessionConfig,
         server_address: String,
         server_port: u16,
     ) -> Self {
@@ -26,7 +34,15 @@ impl Connection {
 
         log::info!("starting player");
         let thread = thread::spawn(move || {
-            ConnectionThread::run(msg_rx, event_tx, request_id, server_address, server_port)
+            ConnectionThread::run(
+                msg_rx,
+                event_tx,
+                request_id,
+                resume_token,
+                session_config,
+                server_address,
+                server_port,
+            )
         });
 
         Self {
@@ -35,10 +51,16 @@ impl Connection {
         }
     }
 
+    /// Tells the server that the host rejected a song suggestion, so that its guest is notified.
+    pub fn reject(&self, song: SongStatus) {
+        // the thread is gone if the server is incompatible, then there is nobody to tell
+        _ = self.msg_tx.send(ThreadMessage::Reject(song));
+    }
+
     fn quit(&self) {
         log::info!("terminating connection");
         let msg = ThreadMessage::Quit;
-        self.msg_tx.send(msg).unwrap();
+        _ = self.msg_tx.send(msg);
     }
 }
 
@@ -52,88 +74,147 @@ impl Drop for Connection {
 }
 
 enum ThreadMessage {
+    Reject(SongStatus),
     Quit,
 }
 
 struct ConnectionThread {
     msg_rx: Receiver<ThreadMessage>,
     event_tx: Sender<Event>,
-    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
     server_address: String,
     server_port: u16,
+    request_id: Option<String>,
+    resume_token: Option<String>,
+    session_config: SessionConfig,
+    backoff: Duration,
+    last_status: Option<PlayerStatus>,
+    last_status_check: Instant,
+    last_filter: Option<SongFilter>,
+    /// Rejected songs that have not been reported to the server yet.
+    rejected: Vec<SongStatus>,
+}
+
+enum Status {
+    Running,
+    Quit,
+    Closed,
+    Incompatible,
 }
 
 impl ConnectionThread {
+    const MIN_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(60);
+    const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
     fn run(
         msg_rx: Receiver<ThreadMessage>,
         event_tx: Sender<Event>,
         request_id: Option<String>,
+        resume_token: Option<String>,
+        session_config: SessionConfig,
         server_address: String,
         server_port: u16,
     ) {
-        let mut socket = match Self::open_socket(&server_address, server_port) {
-            Ok(socket) => socket,
-            Err(e) => {
-                log::info!("failed to connect to server: {e}");
-                let msg = e.to_string();
-                event_tx.send(Event::ConnError { msg }).unwrap();
-                return;
-            }
-        };
-
-        let msg = match request_id {
-            Some(request_id) => {
-                log::info!("requesting id {request_id}");
-                format!("hello:{request_id}")
-            }
-            None => "hello".to_owned(),
-        };
-        if let Err(e) = socket.send(Message::Text(msg)) {
-            log::info!("failed to send hello to server: {e}");
-            let msg = e.to_string();
-            event_tx.send(Event::ConnError { msg }).unwrap();
-            return;
-        }
-
         let mut connection = Self {
-            socket,
             msg_rx,
             event_tx,
+            socket: None,
             server_address,
             server_port,
+            request_id,
+            resume_token,
+            session_config,
+            backoff: Self::MIN_BACKOFF,
+            last_status: None,
+            last_status_check: Instant::now(),
+            last_filter: None,
+            rejected: Vec::new(),
         };
 
         loop {
-            match connection.run_iter() {
-                Ok(true) => continue,
-                Ok(false) => {
-                    log::error!("connection quit");
-                    connection.event_tx.send(Event::ServerClose).unwrap();
-                    break;
-                }
+            let msg = match connection.connect() {
+                Ok(()) => match connection.serve() {
+                    Ok(Status::Quit) | Ok(Status::Incompatible) => return,
+                    Ok(Status::Running) | Ok(Status::Closed) => {
+                        log::error!("connection closed by server");
+                        "connection closed".to_owned()
+                    }
+                    Err(e) => {
+                        log::error!("connection handling failed: {e}");
+                        e.to_string()
+                    }
+                },
                 Err(e) => {
-                    log::error!("connection handling failed: {e}");
-                    let msg = e.to_string();
-                    connection.event_tx.send(Event::ConnError { msg }).unwrap();
-                    return;
+                    log::error!("failed to connect to server: {e}");
+                    e.to_string()
                 }
+            };
+
+            connection.socket = None;
+            let backoff = connection.backoff;
+            log::info!("reconnecting in {}s", backoff.as_secs());
+            let msg = format!("{msg} (reconnecting in {}s)", backoff.as_secs());
+            connection.event_tx.send(Event::ConnError { msg }).unwrap();
+
+            match connection.msg_rx.recv_timeout(backoff) {
+                Ok(ThreadMessage::Quit) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(ThreadMessage::Reject(song)) => connection.rejected.push(song),
+                Err(RecvTimeoutError::Timeout) => (),
             }
+
+            connection.backoff = (backoff * 2).min(Self::MAX_BACKOFF);
         }
     }
 
-    fn run_iter(&mut self) -> Result<bool> {
+    fn connect(&mut self) -> Result<()> {
+        self.socket = Some(Self::open_socket(&self.server_address, self.server_port)?);
+        self.last_status = None;
+        self.last_filter = None;
+
+        if let Some(ref request_id) = self.request_id {
+            log::info!("requesting id {request_id}");
+        }
+        self.send(&ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            request_id: self.request_id.clone(),
+            resume_token: self.resume_token.clone(),
+        })?;
+        self.send(&ClientMessage::Config(self.session_config.clone()))
+    }
+
+    fn serve(&mut self) -> Result<Status> {
+        loop {
+            match self.run_iter()? {
+                Status::Running => continue,
+                status => return Ok(status),
+            }
+        }
+    }
+
+    fn run_iter(&mut self) -> Result<Status> {
         match self.msg_rx.try_recv() {
             Ok(ThreadMessage::Quit) | Err(TryRecvError::Disconnected) => {
-                _ = self.socket.close(None);
-                return Ok(false);
+                _ = self.socket.as_mut().unwrap().close(None);
+                return Ok(Status::Quit);
             }
+            Ok(ThreadMessage::Reject(song)) => self.rejected.push(song),
             Err(TryRecvError::Empty) => (),
         }
 
-        match self.socket.read() {
-            Ok(Message::Ping(d)) => self.socket.send(Message::Pong(d))?,
-            Ok(Message::Close(_)) => return Ok(false),
-            Ok(Message::Text(t)) => self.handle_message(&t),
+        // songs are only removed once they were sent, so that none is lost if sending fails
+        while let Some(song) = self.rejected.first() {
+            self.send(&ClientMessage::Rejected(song.clone()))?;
+            self.rejected.remove(0);
+        }
+
+        self.report_status()?;
+
+        let socket = self.socket.as_mut().unwrap();
+        match socket.read() {
+            Ok(Message::Ping(d)) => socket.send(Message::Pong(d))?,
+            Ok(Message::Close(_)) => return Ok(Status::Closed),
+            Ok(Message::Text(t)) => return self.handle_message(&t),
             Ok(_) => (),
             Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => {
                 thread::sleep(Duration::from_millis(50))
@@ -141,27 +222,89 @@ impl ConnectionThread {
             Err(e) => Err(e)?,
         }
 
-        Ok(true)
+        Ok(Status::Running)
+    }
+
+    fn report_status(&mut self) -> Result<()> {
+        if self.last_status_check.elapsed() < Self::STATUS_INTERVAL {
+            return Ok(());
+        }
+        self.last_status_check = Instant::now();
+
+        let filter = blocklist::get().filter().clone();
+        if self.last_filter.as_ref() != Some(&filter) {
+            self.send(&ClientMessage::Filter(filter.clone()))?;
+            self.last_filter = Some(filter);
+        }
+
+        let mut status = state::get().status();
+        status.recently_played = history::get().recent_status();
+        if self.last_status.as_ref() == Some(&status) {
+            return Ok(());
+        }
+
+        self.send(&ClientMessage::Status(status.clone()))?;
+        self.last_status = Some(status);
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &ClientMessage) -> Result<()> {
+        let msg = Message::Text(protocol::encode(msg));
+        match self.socket.as_mut().unwrap().send(msg) {
+            // the message is buffered and will be flushed on the next write
+            Err(Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            res => Ok(res?),
+        }
     }
 
-    fn handle_message(&self, s: &str) {
-        if let Some(id) = s.strip_prefix("hello:") {
-            if !id.is_empty() {
-                let id = id.to_owned();
+    fn handle_message(&mut self, s: &str) -> Result<Status> {
+        match protocol::decode::<ServerMessage>(s) {
+            Ok(ServerMessage::Hello { id, resume_token }) => {
                 log::info!("connected with id {id}");
                 log::info!(
                     "submission url: {}",
                     util::submission_url(&id, &self.server_address, self.server_port)
                 );
-                self.event_tx.send(Event::ServerHello { id }).unwrap();
+                // remember the assigned id so that it can be reclaimed after a reconnect
+                self.request_id = Some(id.clone());
+                self.resume_token = Some(resume_token.clone());
+                self.backoff = Self::MIN_BACKOFF;
+                self.event_tx
+                    .send(Event::ServerHello { id, resume_token })
+                    .unwrap();
             }
-        } else if let Some(song_id) = s.strip_prefix("push:") {
-            if song_id.len() == 11 {
-                let song_id = song_id.to_owned();
-                log::info!("received new song {song_id}");
-                self.event_tx.send(Event::Push { song_id }).unwrap();
+            Ok(ServerMessage::UnsupportedVersion { version }) => {
+                let msg = format!(
+                    "unsupported protocol version (server: {version}, client: {PROTOCOL_VERSION})"
+                );
+                log::error!("{msg}");
+                self.event_tx.send(Event::ConnError { msg }).unwrap();
+                return Ok(Status::Incompatible);
+            }
+            Ok(ServerMessage::Push {
+                song_id,
+                source,
+                submitter,
+            }) => match SourceRef::from_push(&song_id, source) {
+                Ok(source) => {
+                    log::info!("received new song {source}");
+                    let event = Event::Push { source, submitter };
+                    self.event_tx.send(event).unwrap();
+                }
+                Err(e) => log::warn!("received invalid song: {e}"),
+            },
+            Ok(ServerMessage::MoveUp { song_id }) => {
+                log::info!("listeners voted to move up {song_id}");
+                self.event_tx.send(Event::MoveUp { song_id }).unwrap();
             }
+            Ok(ServerMessage::Skip { song_id }) => {
+                log::info!("listeners voted to skip {song_id}");
+                self.event_tx.send(Event::Skip { song_id }).unwrap();
+            }
+            Err(e) => log::warn!("failed to parse message from server: {e}"),
         }
+
+        Ok(Status::Running)
     }
 
     fn open_socket(
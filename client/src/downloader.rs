@@ -1,55 +1,78 @@
 use std::collections::VecDeque;
 use std::fs;
-use std::io::{BufRead, BufReader, Cursor};
 use std::path::PathBuf;
-use std::process::{Child, Command, ExitStatus, Stdio};
-use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use image::{ImageFormat, ImageReader};
-use reqwest::blocking::Client;
-use serde::Deserialize;
+use anyhow::{anyhow, Result};
 
-use crate::state::{self, Song};
-use crate::util;
+use crate::fallback::Feeder;
+use crate::playlist::PlaylistEntry;
+use crate::source::{self, SourceRef};
+use crate::state::{self, DownloadProgress, DownloadState, Song};
+use crate::{blocklist, cache, util};
 
 /* public api *************************************************************************************/
 
 pub struct Downloader {
     info_tx: Sender<Message>,
+    audio_tx: Sender<Message>,
     info_thread: Option<JoinHandle<()>>,
     audio_thread: Option<JoinHandle<()>>,
 }
 
 impl Downloader {
-    pub fn start(fallback_playlist: Option<Vec<String>>) -> Self {
+    /// Starts downloading. `prefetch` is the number of fallback songs that are downloaded ahead
+    /// of playback.
+    pub fn start(fallback: Option<Feeder>, workers: usize, prefetch: usize) -> Self {
         let (info_tx, info_rx) = mpsc::channel();
         let (audio_tx, audio_rx) = mpsc::channel();
 
         log::info!("starting downloader");
-        let info_thread =
-            thread::spawn(move || InfoDownloaderThread::run(info_rx, audio_tx, fallback_playlist));
-        let audio_thread = thread::spawn(move || AudioDownloaderThread::run(audio_rx));
+        let info_thread = {
+            let audio_tx = audio_tx.clone();
+            thread::spawn(move || InfoDownloaderThread::run(info_rx, audio_tx, fallback, prefetch))
+        };
+        let audio_thread = thread::spawn(move || AudioDownloaderThread::run(audio_rx, workers));
 
         Self {
             info_tx,
+            audio_tx,
             info_thread: Some(info_thread),
             audio_thread: Some(audio_thread),
         }
     }
 
-    pub fn enqueue(&self, id: &str) {
-        log::info!("enqueueing download for {id}");
-        let id = id.to_owned();
+    pub fn enqueue(&self, source: SourceRef, submitter: Option<String>) {
+        if blocklist::get().filter().blocks_id(&source.id()) {
+            log::info!("not downloading {source}, it is blocked");
+            return;
+        }
+
+        log::info!("enqueueing download for {source}");
         let msg = Message::Download {
-            id,
+            source,
             is_fallback: false, // don't care
+            submitter,
         };
         self.info_tx.send(msg).unwrap();
     }
 
+    /// Downloads the audio of a song that was added to the queue without its audio, i.e. after it
+    /// was approved in moderation mode or restored from the journal.
+    pub fn download_audio(&self, source: SourceRef) {
+        log::info!("enqueueing audio download for {source}");
+        let msg = Message::Download {
+            source,
+            is_fallback: false,
+            submitter: None, // don't care
+        };
+        self.audio_tx.send(msg).unwrap();
+    }
+
     fn quit(&self) {
         log::info!("terminating downloader");
         let msg = Message::Quit;
@@ -76,27 +99,30 @@ struct InfoDownloaderThread {
     audio_tx: Sender<Message>,
     queue: VecDeque<DownloadEntry>,
     fallback_queue: VecDeque<DownloadEntry>,
+    fallback: Option<Feeder>,
+    /// Number of fallback songs kept in the fallback queue of the state.
+    prefetch: usize,
+    last_refill: Option<Instant>,
 }
 
 impl InfoDownloaderThread {
     const DOWNLOAD_ATTEMPTS: usize = 3;
+    const REFILL_INTERVAL: Duration = Duration::from_secs(1);
 
     fn run(
         info_rx: Receiver<Message>,
         audio_tx: Sender<Message>,
-        fallback_playlist: Option<Vec<String>>,
+        fallback: Option<Feeder>,
+        prefetch: usize,
     ) {
-        let fallback_queue = fallback_playlist
-            .unwrap_or_default()
-            .into_iter()
-            .map(|id| DownloadEntry { id, tries_left: 5 })
-            .collect::<VecDeque<_>>();
-
         let mut downloader = Self {
             info_rx,
             audio_tx,
-            fallback_queue,
             queue: VecDeque::new(),
+            fallback_queue: VecDeque::new(),
+            fallback,
+            prefetch: prefetch.max(1),
+            last_refill: None,
         };
 
         while downloader.run_iter() {}
@@ -106,16 +132,26 @@ impl InfoDownloaderThread {
 
     fn run_iter(&mut self) -> bool {
         if self.queue.is_empty() && self.fallback_queue.is_empty() {
-            match self.info_rx.recv() {
-                Ok(Message::Download { id, .. }) => self.enqueue(id),
+            self.refill_fallback_queue();
+        }
+
+        // wake up regularly to check whether the fallback playlist has to be refilled
+        if self.queue.is_empty() && self.fallback_queue.is_empty() {
+            match self.info_rx.recv_timeout(Self::REFILL_INTERVAL) {
+                Ok(Message::Download {
+                    source, submitter, ..
+                }) => self.enqueue(source, submitter),
                 Ok(Message::Quit) => return false,
-                Err(_) => return false,
+                Err(RecvTimeoutError::Timeout) => return true,
+                Err(RecvTimeoutError::Disconnected) => return false,
             }
         }
 
         loop {
             match self.info_rx.try_recv() {
-                Ok(Message::Download { id, .. }) => self.enqueue(id),
+                Ok(Message::Download {
+                    source, submitter, ..
+                }) => self.enqueue(source, submitter),
                 Ok(Message::Quit) => return false,
                 Err(TryRecvError::Disconnected) => return false,
                 Err(TryRecvError::Empty) => break,
@@ -129,7 +165,8 @@ impl InfoDownloaderThread {
             true => log::info!("processing {} (fallback)", entry.id),
         }
 
-        if entry.is_cached() {
+        // the song info of local files is read from the file the first time they are played
+        if entry.is_cached() && entry.song_info_cache_location().exists() {
             log::info!("file {} in cache, skipping download", entry.id);
             match self.add_to_state_queue_from_cache(&entry, is_fallback) {
                 Ok(()) => return true,
@@ -137,35 +174,80 @@ impl InfoDownloaderThread {
             }
         }
 
-        let song_info = match self.fetch_song_info(&entry.id) {
+        let mut song_info = match source::get(&entry.source).fetch_info(&entry.source) {
             Ok(song_info) => song_info,
             Err(e) => {
                 log::error!("failed to fetch song info for {}: {e}", entry.id);
-                self.requeue(entry);
+                self.requeue(entry, is_fallback);
                 return true;
             }
         };
+        if let Some(ref title) = entry.title {
+            song_info.title.clone_from(title);
+        }
+        if let Some(ref artist) = entry.artist {
+            song_info.artist.clone_from(artist);
+        }
 
         if let Err(e) = self.save_to_cache(&entry, &song_info) {
             log::warn!("failed to save song info for {} to cache: {e}", entry.id);
         };
 
-        self.add_to_state_queue(song_info, is_fallback);
-        self.audio_tx
-            .send(Message::Download {
-                id: entry.id,
-                is_fallback,
-            })
-            .unwrap();
+        if !self.is_allowed(&song_info) {
+            if is_fallback {
+                state::get().remove_from_fallback_playlist(&entry.id);
+            }
+            return true;
+        }
+
+        // songs pending approval are downloaded once the host approves them
+        if self.add_to_state_queue(song_info, &entry, is_fallback) {
+            self.audio_tx
+                .send(Message::Download {
+                    source: entry.source,
+                    is_fallback,
+                    submitter: None, // don't care
+                })
+                .unwrap();
+        }
 
         true
     }
 
-    fn enqueue(&mut self, id: String) {
-        self.queue.push_back(DownloadEntry {
-            id,
-            tries_left: Self::DOWNLOAD_ATTEMPTS,
-        });
+    fn enqueue(&mut self, source: SourceRef, submitter: Option<String>) {
+        let entry = DownloadEntry::new(source, Self::DOWNLOAD_ATTEMPTS, submitter);
+        self.queue.push_back(entry);
+    }
+
+    /// Tops up the fallback queue of the state with the next songs of the fallback playlists. The
+    /// songs are only resolved and downloaded a few songs ahead of playback, so that large
+    /// playlists neither delay the start nor flood the cache. Runs at most once per interval, as
+    /// songs that are already queued or playing are not queued again.
+    fn refill_fallback_queue(&mut self) {
+        let Some(ref mut fallback) = self.fallback else {
+            return;
+        };
+        if self
+            .last_refill
+            .is_some_and(|at| at.elapsed() < Self::REFILL_INTERVAL)
+        {
+            return;
+        }
+        self.last_refill = Some(Instant::now());
+
+        let mut state = state::get();
+        state.set_mood(fallback.mood());
+        let missing = self.prefetch.saturating_sub(state.fallback_queue().count());
+
+        let songs = (0..missing)
+            .map_while(|_| fallback.next_song())
+            .collect::<Vec<_>>();
+        state.extend_fallback_playlist(songs.iter().map(|song| song.source.clone()).collect());
+        state.set_upcoming_fallback(fallback.upcoming());
+        drop(state);
+
+        self.fallback_queue
+            .extend(songs.into_iter().map(DownloadEntry::fallback));
     }
 
     // returns Option<(entry, is_fallback)>
@@ -176,66 +258,40 @@ impl InfoDownloaderThread {
         }
     }
 
-    fn requeue(&mut self, entry: DownloadEntry) {
+    fn requeue(&mut self, entry: DownloadEntry, is_fallback: bool) {
+        let queue = match is_fallback {
+            false => &mut self.queue,
+            true => &mut self.fallback_queue,
+        };
+
         match entry.tries_left {
-            0 => log::warn!("skipping download of {} due to excessive errors", entry.id),
-            tries_left => self.queue.push_front(DownloadEntry {
-                id: entry.id,
+            0 => {
+                log::warn!("skipping download of {} due to excessive errors", entry.id);
+                if is_fallback {
+                    state::get().remove_from_fallback_playlist(&entry.id);
+                }
+            }
+            tries_left => queue.push_front(DownloadEntry {
                 tries_left: tries_left - 1,
+                ..entry
             }),
         }
     }
 
-    fn fetch_song_info(&self, id: &str) -> Result<Song> {
-        log::info!("fetching song info for {id}");
-
-        let client = Client::new();
-        let request_url = format!(
-            "https://www.youtube.com/oembed?format=json&url=https://www.youtube.com/watch?v={id}"
-        );
-        let mut response = client
-            .get(request_url)
-            .send()?
-            .json::<YoutubeVideoResponse>()?;
-
-        if response.author_name.ends_with(" - Topic") {
-            response
-                .author_name
-                .truncate(response.author_name.len() - 8);
-        }
-        response.thumbnail_url = response
-            .thumbnail_url
-            .replace("hqdefault.jpg", "maxresdefault.jpg");
-
-        log::info!(
-            "got song info for {id}: {} / {}",
-            response.title,
-            response.author_name
-        );
-
-        log::info!("fetching thumbnail for {id} at {}", response.thumbnail_url);
-
-        let client = Client::new();
-        let orig_thumbnail = client.get(&response.thumbnail_url).send()?.bytes()?;
-
-        let mut thumbnail = Vec::new();
-        ImageReader::new(Cursor::new(orig_thumbnail))
-            .with_guessed_format()?
-            .decode()?
-            .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)?;
-
-        Ok(Song {
-            id: id.to_owned(),
-            title: response.title,
-            artist: response.author_name,
-            downloaded: false,
-            thumbnail,
-        })
-    }
-
-    fn add_to_state_queue(&self, song_info: Song, is_fallback: bool) {
+    fn add_to_state_queue(
+        &self,
+        mut song_info: Song,
+        entry: &DownloadEntry,
+        is_fallback: bool,
+    ) -> bool {
+        song_info.submitter = entry.submitter.clone();
         let mut state = state::get();
-        state.enqueue(song_info, is_fallback);
+        let queued = state.enqueue(song_info, is_fallback);
+        if is_fallback && !queued {
+            // the song is queued or playing already, so this occurrence is never played
+            state.remove_from_fallback_playlist(&entry.id);
+        }
+        queued
     }
 
     fn add_to_state_queue_from_cache(
@@ -247,10 +303,26 @@ impl InfoDownloaderThread {
         let data = fs::read(path)?;
         let mut song_info = serde_json::from_slice::<Song>(&data)?;
         song_info.downloaded = true; // ok because if song is not downloaded, we re-fetch the song info
-        self.add_to_state_queue(song_info, is_fallback);
+        if self.is_allowed(&song_info) {
+            // already downloaded, so it does not matter whether the song was queued
+            _ = self.add_to_state_queue(song_info, entry, is_fallback);
+        } else if is_fallback {
+            state::get().remove_from_fallback_playlist(&entry.id);
+        }
         Ok(())
     }
 
+    fn is_allowed(&self, song_info: &Song) -> bool {
+        let blocklist = blocklist::get();
+        let allowed = blocklist
+            .filter()
+            .allows(&song_info.id, &song_info.title, &song_info.artist);
+        if !allowed {
+            log::info!("skipping {}, it is blocked", song_info.id);
+        }
+        allowed
+    }
+
     fn save_to_cache(&self, entry: &DownloadEntry, song_info: &Song) -> Result<()> {
         let path = entry.song_info_cache_location();
         let data = serde_json::to_vec(song_info)?;
@@ -260,38 +332,56 @@ impl InfoDownloaderThread {
     }
 }
 
-#[derive(Deserialize)]
-struct YoutubeVideoResponse {
-    author_name: String,
-    thumbnail_url: String,
-    title: String,
-}
-
 /* audio downloader *******************************************************************************/
 
 struct AudioDownloaderThread {
     rx: Receiver<Message>,
     queue: VecDeque<DownloadEntry>,
     fallback_queue: VecDeque<DownloadEntry>,
+    /// Maximum number of concurrent downloads.
+    workers: usize,
+    active: Vec<ActiveDownload>,
+}
+
+/// A download running on its own thread.
+struct ActiveDownload {
+    entry: DownloadEntry,
+    is_fallback: bool,
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
 }
 
 impl AudioDownloaderThread {
     const DOWNLOAD_ATTEMPTS: usize = 3;
 
-    fn run(rx: Receiver<Message>) {
+    fn run(rx: Receiver<Message>, workers: usize) {
         let mut downloader = Self {
             rx,
             queue: VecDeque::new(),
             fallback_queue: VecDeque::new(),
+            workers: workers.max(1),
+            active: Vec::new(),
         };
 
         while downloader.run_iter() {}
+
+        for download in &downloader.active {
+            download.cancel.store(true, Ordering::Relaxed);
+        }
+        for download in downloader.active {
+            _ = download.thread.join();
+            _ = fs::remove_file(download.entry.audio_download_location());
+        }
     }
 
     fn run_iter(&mut self) -> bool {
-        if self.queue.is_empty() {
+        if self.active.is_empty() && self.queue.is_empty() && self.fallback_queue.is_empty() {
             match self.rx.recv() {
-                Ok(Message::Download { id, is_fallback }) => self.enqueue(id, is_fallback),
+                Ok(Message::Download {
+                    source,
+                    is_fallback,
+                    ..
+                }) => self.enqueue(source, is_fallback),
                 Ok(Message::Quit) => return false,
                 Err(_) => return false,
             }
@@ -299,136 +389,230 @@ impl AudioDownloaderThread {
 
         loop {
             match self.rx.try_recv() {
-                Ok(Message::Download { id, is_fallback }) => self.enqueue(id, is_fallback),
+                Ok(Message::Download {
+                    source,
+                    is_fallback,
+                    ..
+                }) => self.enqueue(source, is_fallback),
                 Ok(Message::Quit) => return false,
                 Err(TryRecvError::Disconnected) => return false,
                 Err(TryRecvError::Empty) => break,
             }
         }
 
-        let entry = self.dequeue().unwrap();
-        if entry.is_cached() {
-            log::info!("file {} in cache, skipping download", entry.id);
-            return true;
+        while self.active.len() < self.workers {
+            let Some((entry, is_fallback)) = self.dequeue() else {
+                break;
+            };
+            if entry.is_cached() {
+                log::info!("file {} in cache, skipping download", entry.id);
+                continue;
+            }
+            self.start_download(entry, is_fallback);
         }
 
-        self.download(entry)
+        self.poll_downloads();
+        if !self.active.is_empty() {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        true
     }
 
-    fn enqueue(&mut self, id: String, is_fallback: bool) {
+    fn enqueue(&mut self, source: SourceRef, is_fallback: bool) {
+        let is_known = self
+            .queue
+            .iter()
+            .chain(&self.fallback_queue)
+            .chain(self.active.iter().map(|download| &download.entry))
+            .any(|entry| entry.source == source);
+        if is_known {
+            return;
+        }
+
         let queue = match is_fallback {
             false => &mut self.queue,
             true => &mut self.fallback_queue,
         };
 
-        queue.push_back(DownloadEntry {
-            id,
-            tries_left: Self::DOWNLOAD_ATTEMPTS,
-        });
+        queue.push_back(DownloadEntry::new(source, Self::DOWNLOAD_ATTEMPTS, None));
     }
 
-    fn dequeue(&mut self) -> Option<DownloadEntry> {
-        self.queue.pop_front().or(self.fallback_queue.pop_front())
+    // returns Option<(entry, is_fallback)>
+    fn dequeue(&mut self) -> Option<(DownloadEntry, bool)> {
+        // the song that plays next goes first, even if it was submitted later
+        let next = state::get().next_song_to_download();
+        let position = |queue: &VecDeque<DownloadEntry>| {
+            let next = next.as_ref()?;
+            queue.iter().position(|entry| entry.id == *next)
+        };
+
+        if let Some(index) = position(&self.queue) {
+            return self.queue.remove(index).zip(Some(false));
+        }
+        if let Some(entry) = self.queue.pop_front() {
+            return Some((entry, false));
+        }
+
+        // one worker is kept free for song suggestions, so that they never wait for the fallback
+        // playlist
+        let fallback_downloads = self.active.iter().filter(|d| d.is_fallback).count();
+        if self.workers > 1 && fallback_downloads + 1 >= self.workers {
+            return None;
+        }
+        let index = position(&self.fallback_queue).unwrap_or(0);
+        self.fallback_queue.remove(index).zip(Some(true))
     }
 
-    fn requeue(&mut self, entry: DownloadEntry) {
+    fn requeue(&mut self, entry: DownloadEntry, is_fallback: bool) {
+        let queue = match is_fallback {
+            false => &mut self.queue,
+            true => &mut self.fallback_queue,
+        };
+
         match entry.tries_left {
-            0 => log::warn!("skipping download of {} due to excessive errors", entry.id),
-            tries_left => self.queue.push_front(DownloadEntry {
-                id: entry.id,
+            0 => {
+                log::warn!("skipping download of {} due to excessive errors", entry.id);
+                state::get().fail_download(&entry.id, Self::DOWNLOAD_ATTEMPTS + 1);
+            }
+            tries_left => queue.push_front(DownloadEntry {
                 tries_left: tries_left - 1,
+                ..entry
             }),
         }
     }
 
-    fn download(&mut self, entry: DownloadEntry) -> bool {
-        log::info!("downloading {}", entry.id);
-
-        let mut command = Command::new("yt-dlp");
-        let command = command
-            .arg("--format")
-            .arg("bestaudio[ext=m4a]")
-            .arg("--extract-audio")
-            .arg("--output")
-            .arg(entry.audio_cache_location())
-            .arg(entry.youtube_url())
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped());
-
-        log::info!("executing {command:?}");
-        let mut command = command.spawn().unwrap();
-
-        // TODO: check for quit message while waiting
-        loop {
-            match command.try_wait() {
-                Ok(Some(status)) if status.success() => {
-                    log::info!("{} downloaded successfully", entry.id);
-                    let mut state = state::get();
-                    state.mark_downloaded(&entry.id);
-                    return true;
-                }
-                Ok(Some(status)) => {
-                    self.download_failed(entry, status, &mut command);
-                    return true;
-                }
-                Ok(None) => match self.rx.try_recv() {
-                    Ok(Message::Download { id, is_fallback }) => self.enqueue(id, is_fallback),
-                    Ok(Message::Quit) | Err(TryRecvError::Disconnected) => {
-                        _ = command.kill();
-                        return false;
-                    }
-                    Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(50)),
-                },
-                Err(e) => {
-                    log::error!("failed to wait on command: {e}");
-                    self.requeue(entry);
-                    return true;
-                }
+    fn start_download(&mut self, entry: DownloadEntry, is_fallback: bool) {
+        log::info!("downloading {}", entry.source);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let source = entry.source.clone();
+            let id = entry.id.clone();
+            let output = entry.audio_download_location();
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let progress = |progress: DownloadProgress| {
+                    state::get().set_download_state(&id, DownloadState::Downloading(progress));
+                };
+                source::get(&source).fetch_audio(&source, &output, &progress, &cancel)
+            })
+        };
+
+        self.active.push(ActiveDownload {
+            entry,
+            is_fallback,
+            cancel,
+            thread,
+        });
+    }
+
+    /// Handles the downloads that have finished.
+    fn poll_downloads(&mut self) {
+        let mut index = 0;
+        while index < self.active.len() {
+            if !self.active[index].thread.is_finished() {
+                index += 1;
+                continue;
             }
+
+            let download = self.active.swap_remove(index);
+            let result = download
+                .thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("download thread panicked")));
+            self.download_finished(download.entry, download.is_fallback, result);
         }
     }
 
-    fn download_failed(&mut self, entry: DownloadEntry, status: ExitStatus, command: &mut Child) {
-        log::error!(
-            "{} failed to download with exit code {status}, stderr:",
-            entry.id
-        );
-        let stderr = command.stderr.take().unwrap();
-        let stderr = BufReader::new(stderr);
-        for line in stderr.lines() {
-            log::error!(" | {}", line.unwrap());
-        }
-        self.requeue(entry);
+    fn download_finished(&mut self, entry: DownloadEntry, is_fallback: bool, result: Result<()>) {
+        let duration = match result.and_then(|()| self.move_to_cache(&entry)) {
+            Ok(duration) => duration,
+            Err(e) => {
+                log::error!("failed to download {}: {e}", entry.source);
+                _ = fs::remove_file(entry.audio_download_location());
+                self.requeue(entry, is_fallback);
+                return;
+            }
+        };
+
+        log::info!("{} downloaded successfully", entry.id);
+        let in_use = {
+            let mut state = state::get();
+            state.mark_downloaded(&entry.id);
+            state.song_ids()
+        };
+        cache::get().insert(&entry.id, duration, &in_use);
+    }
+
+    /// Checks that the downloaded file is playable, then moves it into the cache. The cache never
+    /// contains a partially written file this way. Returns the duration of the audio in seconds.
+    fn move_to_cache(&self, entry: &DownloadEntry) -> Result<f64> {
+        let path = entry.audio_download_location();
+        let duration = cache::probe_duration(&path)?;
+        fs::rename(path, entry.audio_cache_location())?;
+        Ok(duration)
     }
 }
 
 /* utilities **************************************************************************************/
 
 enum Message {
-    Download { id: String, is_fallback: bool },
+    Download {
+        source: SourceRef,
+        is_fallback: bool,
+        submitter: Option<String>,
+    },
     Quit,
 }
 
 struct DownloadEntry {
+    source: SourceRef,
+    /// ID of the song, see [`SourceRef::id`].
     id: String,
     tries_left: usize,
+    /// Guest token of the listener who submitted the song.
+    submitter: Option<String>,
+    /// Title given by the fallback playlist, overrides the title of the song info.
+    title: Option<String>,
+    /// Artist given by the fallback playlist, overrides the artist of the song info.
+    artist: Option<String>,
 }
 
 impl DownloadEntry {
+    fn new(source: SourceRef, tries_left: usize, submitter: Option<String>) -> Self {
+        Self {
+            id: source.id(),
+            source,
+            tries_left,
+            submitter,
+            title: None,
+            artist: None,
+        }
+    }
+
+    fn fallback(entry: PlaylistEntry) -> Self {
+        Self {
+            title: entry.title,
+            artist: entry.artist,
+            ..Self::new(entry.source, 5, None)
+        }
+    }
+
     fn audio_cache_location(&self) -> PathBuf {
         util::audio_cache_location(&self.id)
     }
 
+    fn audio_download_location(&self) -> PathBuf {
+        util::audio_download_location(&self.id)
+    }
+
     fn song_info_cache_location(&self) -> PathBuf {
         util::song_info_cache_location(&self.id)
     }
 
+    /// Returns whether the audio can be played without downloading it. Local files always can.
     fn is_cached(&self) -> bool {
-        self.audio_cache_location().exists()
-    }
-
-    fn youtube_url(&self) -> String {
-        format!("https://music.youtube.com/watch?v={}", self.id)
+        self.source.is_local() || cache::check(&self.id)
     }
 }
@@ -0,0 +1,382 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{Local, NaiveTime};
+use clap::ValueEnum;
+use rand::seq::{IndexedRandom, SliceRandom};
+
+use crate::history;
+use crate::playlist::PlaylistEntry;
+use crate::source::SourceRef;
+
+/// Order in which the songs of the fallback playlist are played.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlaybackMode {
+    /// In the order of the playlist, starting over at the end
+    Sequential,
+    /// Shuffled once, repeating the same order at the end
+    Shuffle,
+    /// Shuffled again every time the playlist runs out
+    Loop,
+    /// Shuffled, leaving out songs that were played recently
+    NoRepeat,
+}
+
+/// A fallback playlist as given on the command line:
+/// `<path>[,name=<name>][,weight=<weight>][,time=<HH:MM>-<HH:MM>]`. The path may contain commas,
+/// as the options are split off from the end.
+#[derive(Clone)]
+pub struct PlaylistSpec {
+    pub path: PathBuf,
+    /// Shown in the user interface as the mood while the playlist is active. Defaults to the file
+    /// name without extension.
+    pub name: String,
+    /// Relative share of the songs of the playlist among the songs of all active playlists.
+    pub weight: u32,
+    /// Time of day in which the playlist is active. Playlists without are always active.
+    pub window: Option<TimeWindow>,
+}
+
+impl FromStr for PlaylistSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut path = s;
+        let mut options = Vec::new();
+        while let Some((rest, option)) = path.rsplit_once(',') {
+            let is_option = option.split_once('=').is_some_and(|(key, _)| {
+                !key.is_empty() && key.bytes().all(|b| b.is_ascii_lowercase())
+            });
+            if !is_option {
+                break;
+            }
+            options.push(option);
+            path = rest;
+        }
+
+        let path = PathBuf::from(path);
+        if path.as_os_str().is_empty() {
+            return Err("missing path".to_owned());
+        }
+
+        let mut spec = Self {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path,
+            weight: 1,
+            window: None,
+        };
+        for option in options.into_iter().rev() {
+            match option.split_once('=') {
+                Some(("name", name)) => spec.name = name.to_owned(),
+                Some(("weight", weight)) => {
+                    spec.weight = weight
+                        .parse()
+                        .ok()
+                        .filter(|weight| *weight > 0)
+                        .ok_or_else(|| format!("invalid weight {weight:?}"))?;
+                }
+                Some(("time", window)) => spec.window = Some(window.parse()?),
+                _ => return Err(format!("invalid option {option:?}")),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// Time of day between two times, which may span midnight.
+#[derive(Clone, Copy)]
+pub struct TimeWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => self.start <= time || time < self.end,
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time {time:?}, expected HH:MM"))
+        };
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time window {s:?}, expected HH:MM-HH:MM"))?;
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            return Err(format!(
+                "empty time window {s:?}, leave it out to play all day"
+            ));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Hands out the songs of the fallback playlists. Each song is taken from one of the playlists
+/// that are active at the current time of day, chosen randomly by weight. Every playlist is
+/// played in rounds, a round is one pass through the playlist and the next round is started when
+/// the previous one is used up, so the fallback playlists never run out.
+pub struct Feeder {
+    playlists: Vec<Playlist>,
+    mode: PlaybackMode,
+    /// Songs played less than this long ago are left out in [`PlaybackMode::NoRepeat`].
+    no_repeat: Duration,
+}
+
+struct Playlist {
+    spec: PlaylistSpec,
+    songs: Vec<PlaylistEntry>,
+    /// Order of the first round with [`PlaybackMode::Shuffle`], repeated by all later rounds.
+    shuffled: Option<Vec<PlaylistEntry>>,
+    /// Songs of the current round that have not been handed out yet.
+    round: VecDeque<PlaylistEntry>,
+    /// Last song handed out.
+    last: Option<SourceRef>,
+}
+
+impl Feeder {
+    pub fn new(mode: PlaybackMode, no_repeat: Duration) -> Self {
+        Self {
+            playlists: Vec::new(),
+            mode,
+            no_repeat,
+        }
+    }
+
+    pub fn add_playlist(&mut self, spec: PlaylistSpec, songs: Vec<PlaylistEntry>) {
+        self.playlists.push(Playlist {
+            spec,
+            songs,
+            shuffled: None,
+            round: VecDeque::new(),
+            last: None,
+        });
+    }
+
+    /// Continues the rounds of the previous run of the client with the given songs.
+    pub fn resume(&mut self, remaining: &[SourceRef]) {
+        for playlist in &mut self.playlists {
+            playlist.round = remaining
+                .iter()
+                .filter_map(|source| playlist.songs.iter().find(|song| song.source == *source))
+                .cloned()
+                .collect();
+        }
+    }
+
+    /// Returns the songs left in the current rounds of all playlists.
+    pub fn upcoming(&self) -> Vec<SourceRef> {
+        self.playlists
+            .iter()
+            .flat_map(|playlist| &playlist.round)
+            .map(|song| song.source.clone())
+            .collect()
+    }
+
+    /// Returns the names of the playlists that are active right now, if there is more than one
+    /// playlist to choose from.
+    pub fn mood(&self) -> Option<String> {
+        if self.playlists.len() < 2 {
+            return None;
+        }
+        let names = self
+            .active()
+            .into_iter()
+            .map(|index| self.playlists[index].spec.name.as_str())
+            .collect::<Vec<_>>();
+        Some(names.join(", "))
+    }
+
+    /// Returns the next song of a randomly chosen active playlist.
+    pub fn next_song(&mut self) -> Option<PlaylistEntry> {
+        let active = self.active();
+        let index = *active
+            .choose_weighted(&mut rand::rng(), |index| self.playlists[*index].spec.weight)
+            .ok()?;
+
+        let (mode, no_repeat) = (self.mode, self.no_repeat);
+        let playlist = &mut self.playlists[index];
+        if playlist.round.is_empty() {
+            playlist.start_round(mode, no_repeat);
+        }
+        let song = playlist.round.pop_front()?;
+        playlist.last = Some(song.source.clone());
+        Some(song)
+    }
+
+    /// Returns the indices of the non-empty playlists that are active at the current time. If
+    /// none is, all of them are considered active, so there is always something to play.
+    fn active(&self) -> Vec<usize> {
+        let now = Local::now().time();
+        let non_empty = (0..self.playlists.len())
+            .filter(|index| !self.playlists[*index].songs.is_empty())
+            .collect::<Vec<_>>();
+        let active = non_empty
+            .iter()
+            .copied()
+            .filter(|index| {
+                let window = self.playlists[*index].spec.window;
+                window.is_none_or(|window| window.contains(now))
+            })
+            .collect::<Vec<_>>();
+        match active.is_empty() {
+            true => non_empty,
+            false => active,
+        }
+    }
+}
+
+impl Playlist {
+    fn start_round(&mut self, mode: PlaybackMode, no_repeat: Duration) {
+        let mut rng = rand::rng();
+        let mut round = match mode {
+            PlaybackMode::Sequential => self.songs.clone(),
+            PlaybackMode::Shuffle => self
+                .shuffled
+                .get_or_insert_with(|| {
+                    let mut shuffled = self.songs.clone();
+                    shuffled.shuffle(&mut rng);
+                    shuffled
+                })
+                .clone(),
+            PlaybackMode::Loop => {
+                let mut round = self.songs.clone();
+                round.shuffle(&mut rng);
+                round
+            }
+            PlaybackMode::NoRepeat => self.fresh_songs(no_repeat),
+        };
+
+        // reshuffled rounds may start with the song the previous round ended with
+        if mode != PlaybackMode::Sequential
+            && round.len() > 1
+            && self.last.as_ref() == Some(&round[0].source)
+        {
+            round.swap(0, 1);
+        }
+
+        log::info!(
+            "starting a round of {} songs of fallback playlist {}",
+            round.len(),
+            self.spec.name
+        );
+        self.round = round.into();
+    }
+
+    /// Returns the songs that were not played recently in random order. If all songs were played
+    /// recently, the songs played least recently are returned instead.
+    fn fresh_songs(&self, no_repeat: Duration) -> Vec<PlaylistEntry> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let since = now.saturating_sub(no_repeat).as_secs();
+        let last_played = history::get().last_played();
+        let played_at = |entry: &PlaylistEntry| last_played.get(&entry.source.id()).copied();
+
+        let mut fresh = self
+            .songs
+            .iter()
+            .filter(|entry| played_at(entry).is_none_or(|at| at < since))
+            .cloned()
+            .collect::<Vec<_>>();
+        fresh.shuffle(&mut rand::rng());
+        if !fresh.is_empty() {
+            return fresh;
+        }
+
+        log::info!(
+            "all songs of {} were played recently, playing the oldest ones again",
+            self.spec.name
+        );
+        let mut oldest = self.songs.clone();
+        oldest.sort_by_key(|entry| played_at(entry));
+        oldest.truncate(oldest.len().div_ceil(2));
+        oldest.shuffle(&mut rand::rng());
+        oldest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn time_window_within_a_day() {
+        let window = "08:00-17:30".parse::<TimeWindow>().unwrap();
+        assert!(!window.contains(time("07:59")));
+        assert!(window.contains(time("08:00")));
+        assert!(window.contains(time("12:00")));
+        assert!(window.contains(time("17:29")));
+        assert!(!window.contains(time("17:30")));
+    }
+
+    #[test]
+    fn time_window_across_midnight() {
+        let window = "22:00 - 02:00".parse::<TimeWindow>().unwrap();
+        assert!(window.contains(time("22:00")));
+        assert!(window.contains(time("23:59")));
+        assert!(window.contains(time("00:00")));
+        assert!(window.contains(time("01:59")));
+        assert!(!window.contains(time("02:00")));
+        assert!(!window.contains(time("12:00")));
+    }
+
+    #[test]
+    fn invalid_time_windows() {
+        assert!("08:00".parse::<TimeWindow>().is_err());
+        assert!("8-17".parse::<TimeWindow>().is_err());
+        assert!("08:00-25:00".parse::<TimeWindow>().is_err());
+        assert!("08:00-08:00".parse::<TimeWindow>().is_err());
+    }
+
+    #[test]
+    fn parses_playlist_specs() {
+        let spec = "/music/chill.m3u".parse::<PlaylistSpec>().unwrap();
+        assert_eq!(spec.path, PathBuf::from("/music/chill.m3u"));
+        assert_eq!(spec.name, "chill");
+        assert_eq!(spec.weight, 1);
+        assert!(spec.window.is_none());
+
+        let spec = "party.json,name=Party,weight=3,time=20:00-04:00"
+            .parse::<PlaylistSpec>()
+            .unwrap();
+        assert_eq!(spec.name, "Party");
+        assert_eq!(spec.weight, 3);
+        assert!(spec
+            .window
+            .is_some_and(|window| window.contains(time("01:00"))));
+
+        let spec = "/music/Rock, Pop.m3u,weight=2"
+            .parse::<PlaylistSpec>()
+            .unwrap();
+        assert_eq!(spec.path, PathBuf::from("/music/Rock, Pop.m3u"));
+        assert_eq!(spec.name, "Rock, Pop");
+        assert_eq!(spec.weight, 2);
+    }
+
+    #[test]
+    fn invalid_playlist_specs() {
+        assert!("".parse::<PlaylistSpec>().is_err());
+        assert!("a.m3u,weight=0".parse::<PlaylistSpec>().is_err());
+        assert!("a.m3u,weight=x".parse::<PlaylistSpec>().is_err());
+        assert!("a.m3u,volume=3".parse::<PlaylistSpec>().is_err());
+        assert!("a.m3u,time=20:00".parse::<PlaylistSpec>().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use shared::protocol::{PlayedStatus, SongStatus};
+
+use crate::source::SourceRef;
+use crate::state::PlayingSong;
+
+static HISTORY: Mutex<History> = Mutex::new(History::new());
+
+pub fn get() -> MutexGuard<'static, History> {
+    HISTORY.lock().unwrap()
+}
+
+/// Songs played by the client, oldest first. Every entry is appended to the history file as soon
+/// as the song stops playing, so the history survives restarts.
+pub struct History {
+    entries: Vec<Entry>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    /// Guest token of the listener who submitted the song, `None` for fallback songs.
+    pub submitter: Option<String>,
+    /// Time at which the song started playing as a UNIX timestamp in seconds.
+    pub played_at: u64,
+    pub is_fallback: bool,
+    pub skipped: bool,
+    /// Missing in entries recorded before songs could come from other sources than YouTube.
+    #[serde(default)]
+    source: Option<SourceRef>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    /// One song per line, usable as a fallback playlist
+    Playlist,
+}
+
+impl History {
+    /// Number of songs shown to listeners on the queue page.
+    pub const RECENT: usize = 20;
+
+    const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            path: None,
+        }
+    }
+
+    pub fn load(&mut self, path: PathBuf) -> Result<()> {
+        if path.exists() {
+            let data = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            self.entries = data
+                .lines()
+                .filter(|line| !line.is_empty())
+                .filter_map(|line| match serde_json::from_str(line) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        log::warn!("skipping line in {}: {e}", path.display());
+                        None
+                    }
+                })
+                .collect();
+        }
+        self.path = Some(path);
+        Ok(())
+    }
+
+    pub fn record(&mut self, entry: Entry) {
+        if let Some(ref path) = self.path
+            && let Err(e) = append_entry(path, &entry)
+        {
+            log::error!("failed to add {} to history: {e}", entry.id);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Returns the most recently played songs, latest first.
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().rev().take(count)
+    }
+
+    /// Returns the time each song was played last as a UNIX timestamp, by song ID.
+    pub fn last_played(&self) -> HashMap<String, u64> {
+        let mut last_played = HashMap::new();
+        for entry in &self.entries {
+            last_played.insert(entry.id.clone(), entry.played_at);
+        }
+        last_played
+    }
+
+    pub fn recent_status(&self) -> Vec<PlayedStatus> {
+        self.recent(Self::RECENT)
+            .map(|entry| PlayedStatus {
+                song: SongStatus {
+                    id: entry.id.clone(),
+                    title: entry.title.clone(),
+                    artist: entry.artist.clone(),
+                    downloaded: true,
+                    thumbnail: entry.source().thumbnail_url(),
+                },
+                played_at: entry.played_at,
+                is_fallback: entry.is_fallback,
+                skipped: entry.skipped,
+            })
+            .collect()
+    }
+
+    pub fn export(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Csv => {
+                let mut csv =
+                    "played_at,id,title,artist,submitter,is_fallback,skipped\n".to_owned();
+                for entry in &self.entries {
+                    _ = writeln!(
+                        csv,
+                        "{},{},{},{},{},{},{}",
+                        entry.played_at,
+                        entry.id,
+                        csv_field(&entry.title),
+                        csv_field(&entry.artist),
+                        entry.submitter.as_deref().unwrap_or_default(),
+                        entry.is_fallback,
+                        entry.skipped,
+                    );
+                }
+                Ok(csv)
+            }
+            ExportFormat::Json => Ok(serde_json::to_string_pretty(&self.entries)?),
+            ExportFormat::Playlist => {
+                // skipped songs are left out, they were not wanted the first time either
+                let mut songs: Vec<SourceRef> = Vec::new();
+                for entry in self.entries.iter().filter(|entry| !entry.skipped) {
+                    let source = entry.source();
+                    if !songs.contains(&source) {
+                        songs.push(source);
+                    }
+                }
+                Ok(songs.iter().map(|source| format!("{source}\n")).collect())
+            }
+        }
+    }
+}
+
+impl Entry {
+    pub fn new(playing: &PlayingSong, skipped: bool) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            id: playing.song.id.clone(),
+            title: playing.song.title.clone(),
+            artist: playing.song.artist.clone(),
+            submitter: playing.song.submitter.clone(),
+            played_at: now.saturating_sub(playing.elapsed).as_secs(),
+            is_fallback: playing.is_fallback,
+            skipped,
+            source: Some(playing.song.source()),
+        }
+    }
+
+    pub fn source(&self) -> SourceRef {
+        self.source
+            .clone()
+            .unwrap_or_else(|| SourceRef::YouTube(self.id.clone()))
+    }
+}
+
+fn append_entry(path: &Path, entry: &Entry) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::downloader::Downloader;
+use crate::state::{self, Snapshot, Song};
+use crate::{cache, util};
+
+/* public api *************************************************************************************/
+
+/// Periodically writes the queues and the playing song to disk, so that they survive a crash or
+/// restart of the client.
+pub struct Journal {
+    tx: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Journal {
+    pub fn start() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        log::info!("starting journal");
+        let thread = thread::spawn(move || JournalThread::run(rx));
+
+        Self {
+            tx,
+            thread: Some(thread),
+        }
+    }
+
+    fn quit(&self) {
+        log::info!("terminating journal");
+        let msg = Message::Quit;
+        self.tx.send(msg).unwrap();
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        self.quit();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+/// Reads the snapshot written by the previous run of the client.
+pub fn load() -> Option<Snapshot> {
+    let path = util::journal_location();
+    let data = fs::read(&path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            log::warn!("ignoring journal {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Restores the queues of a snapshot. Songs whose info is in the cache are added to the state
+/// directly, all others are downloaded again.
+pub fn restore(snapshot: Snapshot, downloader: &Downloader) {
+    log::info!(
+        "restoring {} queued songs from journal",
+        snapshot.queue.len() + snapshot.pending_approval.len()
+    );
+
+    let entries = snapshot
+        .queue
+        .into_iter()
+        .map(|entry| (entry, false))
+        .chain(
+            snapshot
+                .pending_approval
+                .into_iter()
+                .map(|entry| (entry, true)),
+        );
+    for (entry, pending_approval) in entries {
+        let id = entry.source.id();
+        let mut song = match read_song_info(&id) {
+            Ok(song) => song,
+            Err(e) => {
+                log::warn!("failed to read song info of {id} from cache: {e}");
+                downloader.enqueue(entry.source, entry.submitter);
+                continue;
+            }
+        };
+
+        song.downloaded = entry.source.is_local() || cache::check(&id);
+        song.submitter = entry.submitter;
+        if !song.downloaded && !pending_approval {
+            downloader.download_audio(entry.source);
+        }
+        state::get().restore(song, pending_approval);
+    }
+
+    state::get().set_resume(snapshot.resume);
+}
+
+/* journal thread *********************************************************************************/
+
+enum Message {
+    Quit,
+}
+
+struct JournalThread {
+    rx: Receiver<Message>,
+    last_snapshot: Option<Snapshot>,
+}
+
+impl JournalThread {
+    const INTERVAL: Duration = Duration::from_secs(2);
+
+    fn run(rx: Receiver<Message>) {
+        let mut journal = Self {
+            rx,
+            last_snapshot: None,
+        };
+
+        loop {
+            let quit = match journal.rx.recv_timeout(Self::INTERVAL) {
+                Ok(Message::Quit) | Err(RecvTimeoutError::Disconnected) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+            };
+
+            if let Err(e) = journal.write() {
+                log::error!("failed to write journal: {e}");
+            }
+
+            if quit {
+                return;
+            }
+        }
+    }
+
+    fn write(&mut self) -> Result<()> {
+        let snapshot = state::get().snapshot();
+        if self.last_snapshot.as_ref() == Some(&snapshot) {
+            return Ok(());
+        }
+
+        // write to a temporary file first, so that a crash never leaves a truncated journal
+        let path = util::journal_location();
+        let tmp_path = path.with_extension("json.tmp");
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&tmp_path, serde_json::to_vec(&snapshot)?)?;
+        fs::rename(tmp_path, path)?;
+
+        self.last_snapshot = Some(snapshot);
+        Ok(())
+    }
+}
+
+fn read_song_info(id: &str) -> Result<Song> {
+    let data = fs::read(util::song_info_cache_location(id))?;
+    Ok(serde_json::from_slice(&data)?)
+}
@@ -1,21 +1,31 @@
 use std::fs;
-use std::io::BufRead;
 use std::sync::mpsc;
+use std::time::Duration;
 
 use clap::Parser;
-use rand::seq::SliceRandom;
+use shared::protocol::SessionConfig;
 
-use crate::cli::Cli;
+use crate::cache::Removal;
+use crate::cli::{CacheCommand, Cli, Command, HistoryCommand};
 use crate::connection::Connection;
 use crate::downloader::Downloader;
+use crate::fallback::Feeder;
+use crate::journal::Journal;
 use crate::player::Player;
 use crate::ui::UI;
 use crate::util::Event;
 
+mod blocklist;
+mod cache;
 mod cli;
 mod connection;
 mod downloader;
+mod fallback;
+mod history;
+mod journal;
 mod player;
+mod playlist;
+mod source;
 mod state;
 mod ui;
 mod util;
@@ -25,30 +35,89 @@ fn main() {
 
     let cli = Cli::parse();
 
-    let fallback_playlist: Option<Vec<String>> = cli.fallback_playlist.map(|path| {
-        let mut ids: Vec<String> = fs::read(path)
-            .expect("failed to read fallback playlist file")
-            .lines()
-            .map(|s| s.unwrap())
-            .filter(|s| s.len() == 11)
-            .collect();
-        let mut rng = rand::rng();
-        ids.shuffle(&mut rng);
-        ids
-    });
+    history::get()
+        .load(util::history_location())
+        .expect("failed to load history");
+    cache::get()
+        .load(cli.cache_size)
+        .expect("failed to load cache index");
+    if let Some(library) = cli.library {
+        source::set_library(library);
+    }
+
+    if let Some(command) = cli.command {
+        run_command(command);
+        return;
+    }
+
+    if let Err(e) = cache::remove_partial_downloads() {
+        log::warn!("failed to remove partial downloads: {e}");
+    }
+
+    let mut snapshot = match cli.no_resume {
+        false => journal::load(),
+        true => None,
+    };
+
+    let fallback = match cli.fallback_playlist.is_empty() {
+        true => None,
+        false => {
+            let no_repeat = Duration::from_secs(cli.no_repeat_hours * 60 * 60);
+            let mut feeder = Feeder::new(cli.fallback_mode, no_repeat);
+            for spec in cli.fallback_playlist {
+                let songs = playlist::load(&spec.path).expect("failed to read fallback playlist");
+                feeder.add_playlist(spec, songs);
+            }
+
+            // continue the fallback playlists of the previous run where they left off
+            if let Some(ref snapshot) = snapshot {
+                feeder.resume(&snapshot.fallback_playlist);
+            }
+            Some(feeder)
+        }
+    };
+
+    blocklist::get()
+        .load(cli.blocklist, cli.allowlist)
+        .expect("failed to load blocklist");
 
     let (event_tx, event_rx) = mpsc::channel();
 
-    let _connection = Connection::start(
+    let session_config = SessionConfig {
+        upvote_threshold: cli.upvote_threshold,
+        skip_threshold: cli.skip_threshold,
+        max_songs_per_guest: cli.max_songs_per_guest,
+        hide_explicit: cli.hide_explicit,
+        max_duration: cli.max_duration,
+    };
+    state::get().set_max_songs_per_guest(cli.max_songs_per_guest as usize);
+    state::get().set_moderated(cli.moderate);
+
+    // the server keeps the ID of the previous run reserved for a while, during which it can be
+    // reclaimed with the resume token the server handed out
+    let session = snapshot
+        .as_mut()
+        .and_then(|snapshot| snapshot.session.take())
+        .filter(|session| cli.request_id.as_ref() == Some(&session.id));
+    let resume_token = session.as_ref().map(|session| session.resume_token.clone());
+    state::get().set_session(session);
+
+    let connection = Connection::start(
         event_tx.clone(),
         cli.request_id,
+        resume_token,
+        session_config,
         cli.server_address.clone(),
         cli.server_port,
     );
 
     let _ui = UI::start(event_tx, cli.server_address, cli.server_port);
-    let downloader = Downloader::start(fallback_playlist);
+    let downloader = Downloader::start(fallback, cli.download_workers, cli.fallback_prefetch);
+    if let Some(snapshot) = snapshot {
+        journal::restore(snapshot, &downloader);
+    }
     let player = Player::start();
+    let _journal = Journal::start();
 
     //downloader.enqueue("YBdyc1WDlBQ");
     //downloader.enqueue("1eQWdpWjXlk");
@@ -63,10 +132,93 @@ fn main() {
             Event::UIQuit => break,
             Event::NextSong => player.next(),
             Event::TogglePause => player.toggle_pause(),
-            Event::ServerHello { id } => state::get().set_connected(id),
+            Event::ServerHello { id, resume_token } => state::get().set_connected(id, resume_token),
             Event::ConnError { msg } => state::get().set_connection_error(msg),
-            Event::Push { song_id } => downloader.enqueue(&song_id),
-            Event::ServerClose => state::get().set_connection_error("connection closed".to_owned()),
+            Event::Push { source, submitter } => downloader.enqueue(source, submitter),
+            Event::BlockSong { id } => blocklist::get().block_id(&id),
+            Event::ApproveSong => {
+                let approved = state::get().approve_next();
+                if let Some((source, needs_download)) = approved
+                    && needs_download
+                {
+                    downloader.download_audio(source);
+                }
+            }
+            Event::RejectSong => {
+                let rejected = state::get().reject_next();
+                if let Some(song) = rejected {
+                    log::info!("rejected {}", song.id);
+                    connection.reject(song.status());
+                }
+            }
+            Event::MoveUp { song_id } => state::get().move_up_song(&song_id),
+            Event::Skip { song_id } => {
+                // the vote might have been for a song that already finished playing
+                let playing = state::get()
+                    .playing()
+                    .map(|playing| playing.song.id.clone());
+                if playing.as_deref() == Some(&song_id) {
+                    player.next();
+                }
+            }
+        }
+    }
+}
+
+fn run_command(command: Command) {
+    match command {
+        Command::History {
+            command: HistoryCommand::Export { format, output },
+        } => {
+            let export = history::get()
+                .export(format)
+                .expect("failed to export history");
+            match output {
+                Some(path) => fs::write(path, export).expect("failed to write export"),
+                None => print!("{export}"),
+            }
+        }
+        Command::Cache { command } => run_cache_command(command),
+    }
+}
+
+fn run_cache_command(command: CacheCommand) {
+    let mut cache = cache::get();
+    match command {
+        CacheCommand::List => {
+            let mut entries = cache.entries().collect::<Vec<_>>();
+            entries.sort_by_key(|(_, entry)| entry.last_played.unwrap_or(entry.added_at));
+            for (id, entry) in entries {
+                let title = fs::read(util::song_info_cache_location(id))
+                    .ok()
+                    .and_then(|data| serde_json::from_slice::<state::Song>(&data).ok())
+                    .map(|song| format!("{} - {}", song.artist, song.title))
+                    .unwrap_or_default();
+                let size = entry.size as f64 / (1024.0 * 1024.0);
+                println!("{id}  {size:>7.1} MB  {title}");
+            }
+            let total = cache.total_size() as f64 / (1024.0 * 1024.0);
+            println!("total: {total:.1} MB");
+        }
+        CacheCommand::Verify => {
+            let removed = cache.verify().expect("failed to verify cache");
+            for (id, removal) in &removed {
+                match removal {
+                    Removal::Missing => println!("{id} is missing, removed it from the index"),
+                    Removal::Corrupt => println!("{id} is corrupt, deleted it"),
+                }
+            }
+            println!("{} entries ok", cache.entries().count());
+        }
+        CacheCommand::Prune => {
+            // keep the songs that are restored when the client starts the next time
+            let in_use = journal::load()
+                .map(|snapshot| snapshot.song_ids())
+                .unwrap_or_default();
+            for id in cache.prune(&in_use) {
+                println!("deleted {id}");
+            }
         }
+        CacheCommand::Clear => cache.clear().expect("failed to clear cache"),
     }
 }
@@ -5,7 +5,8 @@ use std::time::Duration;
 use libmpv2::events::{Event, EventContext};
 use libmpv2::Mpv;
 
-use crate::{state, util};
+use crate::source::SourceRef;
+use crate::{cache, history, state};
 
 pub struct Player {
     tx: Sender<Message>,
@@ -57,6 +58,14 @@ enum Message {
     TogglePause,
 }
 
+/// How playback of a song ended.
+enum Outcome {
+    Finished,
+    Skipped,
+    Failed,
+    Quit,
+}
+
 struct PlayerThread {
     rx: Receiver<Message>,
 }
@@ -76,32 +85,54 @@ impl PlayerThread {
             Err(TryRecvError::Empty) => (),
         }
 
-        let Some(next_song_id) = self.get_next_song() else {
+        let Some(next_song) = self.get_next_song() else {
             thread::sleep(Duration::from_millis(50));
             return true;
         };
 
-        match self.play(&next_song_id) {
-            Ok(quit) => quit,
-            Err(e) => {
-                log::error!("failed to play {next_song_id}: {e}");
-                true
-            }
+        match self.play(&next_song) {
+            Ok(Outcome::Finished) => self.record(false),
+            Ok(Outcome::Skipped) => self.record(true),
+            Ok(Outcome::Failed) => (),
+            Ok(Outcome::Quit) => return false,
+            Err(e) => log::error!("failed to play {next_song}: {e}"),
+        }
+        true
+    }
+
+    fn record(&self, skipped: bool) {
+        let entry = state::get()
+            .playing()
+            .map(|playing| history::Entry::new(playing, skipped));
+        if let Some(entry) = entry {
+            history::get().record(entry);
         }
     }
 
-    fn get_next_song(&self) -> Option<String> {
+    fn get_next_song(&self) -> Option<SourceRef> {
         let mut state = state::get();
         state.get_next_song()
     }
 
-    fn play(&self, id: &str) -> Result<bool, libmpv2::Error> {
-        log::info!("playing {id}");
-
-        let path = util::audio_cache_location(id);
-        let path = path.to_str().unwrap();
+    fn play(&self, source: &SourceRef) -> Result<Outcome, libmpv2::Error> {
+        log::info!("playing {source}");
+        let id = &source.id();
+        cache::get().touch(id);
+
+        let path = source.audio_location();
+        let Some(path) = path.to_str() else {
+            log::error!(
+                "cannot play {}, its path is not valid UTF-8",
+                path.display()
+            );
+            return Ok(Outcome::Failed);
+        };
 
         let mpv = Mpv::new()?;
+        if let Some(start) = state::get().take_resume(id) {
+            log::info!("resuming {id} at {}s", start.as_secs());
+            mpv.set_property("start", format!("+{}", start.as_secs()))?;
+        }
         mpv.command("loadfile", &[path, "replace"])?;
 
         let mut event = EventContext::new(mpv.ctx);
@@ -109,13 +140,13 @@ impl PlayerThread {
 
         loop {
             match self.rx.try_recv() {
-                Ok(Message::Quit) => return Ok(false),
-                Ok(Message::Next) => return Ok(true),
+                Ok(Message::Quit) => return Ok(Outcome::Quit),
+                Ok(Message::Next) => return Ok(Outcome::Skipped),
                 Ok(Message::TogglePause) => {
                     paused = !paused;
                     mpv.set_property("pause", paused)?;
                 }
-                Err(TryRecvError::Disconnected) => return Ok(false),
+                Err(TryRecvError::Disconnected) => return Ok(Outcome::Quit),
                 Err(TryRecvError::Empty) => (),
             }
 
@@ -127,16 +158,16 @@ impl PlayerThread {
             match event.wait_event(0.05) {
                 Some(Ok(Event::Shutdown)) => {
                     log::info!("done playing {id}");
-                    return Ok(true);
+                    return Ok(Outcome::Finished);
                 }
                 Some(Ok(Event::EndFile(r))) => {
                     log::info!("mpv reached endfile {id} with reason {r}");
-                    return Ok(true);
+                    return Ok(Outcome::Finished);
                 }
                 Some(Ok(_)) => (),
                 Some(Err(e)) => {
                     log::warn!("mpv got error: {e}");
-                    return Ok(true);
+                    return Ok(Outcome::Failed);
                 }
                 None => (),
             }
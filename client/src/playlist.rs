@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::source::{self, SourceRef};
+
+/// A song of a fallback playlist.
+#[derive(Clone)]
+pub struct PlaylistEntry {
+    pub source: SourceRef,
+    /// Title given by the playlist, overrides the title of the song info.
+    pub title: Option<String>,
+    /// Artist given by the playlist, overrides the artist of the song info.
+    pub artist: Option<String>,
+}
+
+impl PlaylistEntry {
+    fn new(source: SourceRef) -> Self {
+        Self {
+            source,
+            title: None,
+            artist: None,
+        }
+    }
+}
+
+/// Reads a fallback playlist. The format is detected from the extension and the first line:
+///
+/// - M3U and M3U8 with optional `#EXTINF` titles,
+/// - PLS,
+/// - JSON, see [`JsonEntry`],
+/// - plain text with one song per line and `#` comments.
+///
+/// Songs are given as anything [`SourceRef`] parses, YouTube playlist URLs and local directories
+/// are expanded to the songs in them, and relative paths are resolved against the directory of the
+/// playlist. A local audio file or directory is a playlist by itself. Lines that cannot be parsed
+/// are skipped with a warning that names the line.
+pub fn load(path: &Path) -> Result<Vec<PlaylistEntry>> {
+    if path.is_dir() || source::is_audio_file(path) {
+        let path = fs::canonicalize(path)
+            .with_context(|| format!("failed to resolve {}", path.display()))?;
+        return Ok(source::expand(SourceRef::Local(path))?
+            .into_iter()
+            .map(PlaylistEntry::new)
+            .collect());
+    }
+
+    let data =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let parser = Parser {
+        path,
+        base_dir: path.parent().unwrap_or(Path::new(".")),
+        entries: Vec::new(),
+    };
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let first_line = data.lines().map(str::trim).find(|line| !line.is_empty());
+    let entries = match (extension.as_str(), first_line) {
+        ("json", _) => parser.parse_json(&data)?,
+        ("m3u" | "m3u8", _) | (_, Some("#EXTM3U")) => parser.parse_m3u(&data),
+        ("pls", _) | (_, Some("[playlist]")) => parser.parse_pls(&data),
+        _ => parser.parse_plain(&data),
+    };
+
+    log::info!("read {} songs from {}", entries.len(), path.display());
+    Ok(entries)
+}
+
+/// An entry of a JSON playlist, which is an array of these. An entry is either a song given as a
+/// string, or an object with the song in `source` and optionally `title` and `artist`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonEntry {
+    Source(String),
+    Song {
+        source: String,
+        title: Option<String>,
+        artist: Option<String>,
+    },
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    base_dir: &'a Path,
+    entries: Vec<PlaylistEntry>,
+}
+
+impl Parser<'_> {
+    fn parse_plain(mut self, data: &str) -> Vec<PlaylistEntry> {
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.add(&format!("line {}", index + 1), line, None, None);
+        }
+        self.entries
+    }
+
+    fn parse_m3u(mut self, data: &str) -> Vec<PlaylistEntry> {
+        // `#EXTINF:<duration>,<artist> - <title>` describes the song on the next line
+        let mut info = None;
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                info = extinf.split_once(',').map(|(_, name)| split_name(name));
+            } else if !line.is_empty() && !line.starts_with('#') {
+                let (artist, title) = info.take().unwrap_or_default();
+                self.add(&format!("line {}", index + 1), line, title, artist);
+            }
+        }
+        self.entries
+    }
+
+    fn parse_pls(mut self, data: &str) -> Vec<PlaylistEntry> {
+        // `FileN=` and `TitleN=` keys belong to the N-th song, songs are sorted by N
+        let mut files = Vec::new();
+        let mut titles = Vec::new();
+        for (index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('[') || line.starts_with(';') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                self.warn(index + 1, "expected `<key>=<value>`");
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+            if let Some(number) = key.strip_prefix("file") {
+                match number.parse::<usize>() {
+                    Ok(number) => files.push((number, index + 1, value)),
+                    Err(_) => self.warn(index + 1, &format!("invalid key {key:?}")),
+                }
+            } else if let Some(number) = key.strip_prefix("title") {
+                match number.parse::<usize>() {
+                    Ok(number) => titles.push((number, value)),
+                    Err(_) => self.warn(index + 1, &format!("invalid key {key:?}")),
+                }
+            }
+        }
+
+        files.sort_by_key(|(number, _, _)| *number);
+        for (number, line, file) in files {
+            let (artist, title) = titles
+                .iter()
+                .find(|(n, _)| *n == number)
+                .map(|(_, name)| split_name(name))
+                .unwrap_or_default();
+            self.add(&format!("line {line}"), file, title, artist);
+        }
+        self.entries
+    }
+
+    fn parse_json(mut self, data: &str) -> Result<Vec<PlaylistEntry>> {
+        let json_entries = serde_json::from_str::<Vec<JsonEntry>>(data)
+            .with_context(|| format!("failed to parse {}", self.path.display()))?;
+
+        // serde does not keep the position of array elements, so entries are numbered instead
+        for (index, entry) in json_entries.into_iter().enumerate() {
+            let position = format!("entry {}", index + 1);
+            match entry {
+                JsonEntry::Source(source) => self.add(&position, &source, None, None),
+                JsonEntry::Song {
+                    source,
+                    title,
+                    artist,
+                } => self.add(&position, &source, title, artist),
+            }
+        }
+        Ok(self.entries)
+    }
+
+    fn add(&mut self, position: &str, s: &str, title: Option<String>, artist: Option<String>) {
+        let sources = match self.resolve(s) {
+            Ok(sources) => sources,
+            Err(e) => {
+                log::warn!("skipping {position} of {}: {e}", self.path.display());
+                return;
+            }
+        };
+
+        // titles only make sense for single songs, not for expanded directories or playlists
+        if let [source] = &sources[..] {
+            self.entries.push(PlaylistEntry {
+                source: source.clone(),
+                title,
+                artist,
+            });
+        } else {
+            let entries = sources.into_iter().map(PlaylistEntry::new);
+            self.entries.extend(entries);
+        }
+    }
+
+    fn resolve(&self, s: &str) -> Result<Vec<SourceRef>> {
+        if let Some(videos) = source::expand_playlist_url(s) {
+            return videos;
+        }
+
+        let source = match s.parse::<SourceRef>() {
+            Ok(source) => source,
+            // playlists written by music players refer to files relative to the playlist
+            Err(_) if !s.contains("://") => SourceRef::Local(self.base_dir.join(s)),
+            Err(e) => bail!(e),
+        };
+        if let SourceRef::Local(ref path) = source
+            && !path.exists()
+        {
+            bail!("{} does not exist", path.display());
+        }
+        source::expand(source)
+    }
+
+    fn warn(&self, line: usize, msg: &str) {
+        log::warn!("skipping line {line} of {}: {msg}", self.path.display());
+    }
+}
+
+/// Splits a song name of the form `<artist> - <title>`. Returns `(artist, title)`.
+fn split_name(name: &str) -> (Option<String>, Option<String>) {
+    let name = name.trim();
+    if name.is_empty() {
+        return (None, None);
+    }
+    match name.split_once(" - ") {
+        Some((artist, title)) => (
+            Some(artist.trim().to_owned()),
+            Some(title.trim().to_owned()),
+        ),
+        None => (None, Some(name.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, process};
+
+    use super::*;
+
+    const VIDEO: &str = "dQw4w9WgXcQ";
+
+    /// Directory with the empty audio files `a.mp3` and `b.mp3`, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("schmu-playlist-{name}-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for file in ["a.mp3", "b.mp3"] {
+                fs::write(dir.join(file), b"").unwrap();
+            }
+            Self(dir)
+        }
+
+        fn parser(&self) -> Parser<'_> {
+            Parser {
+                path: &self.0,
+                base_dir: &self.0,
+                entries: Vec::new(),
+            }
+        }
+
+        fn local(&self, file: &str) -> SourceRef {
+            SourceRef::Local(self.0.join(file))
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sources(entries: &[PlaylistEntry]) -> Vec<SourceRef> {
+        entries.iter().map(|entry| entry.source.clone()).collect()
+    }
+
+    fn names(entries: &[PlaylistEntry]) -> Vec<(Option<&str>, Option<&str>)> {
+        entries
+            .iter()
+            .map(|entry| (entry.artist.as_deref(), entry.title.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(
+            split_name(" Rick Astley - Never Gonna Give You Up "),
+            (
+                Some("Rick Astley".to_owned()),
+                Some("Never Gonna Give You Up".to_owned())
+            )
+        );
+        assert_eq!(split_name("Intro"), (None, Some("Intro".to_owned())));
+        assert_eq!(split_name(" "), (None, None));
+    }
+
+    #[test]
+    fn parses_plain_playlists() {
+        let dir = TestDir::new("plain");
+        let data = format!(
+            "# comment\n{VIDEO}\n\na.mp3\nhttps://example.com/song.mp3\nmissing.mp3\nftp://x/y\n"
+        );
+        let entries = dir.parser().parse_plain(&data);
+        assert_eq!(
+            sources(&entries),
+            [
+                SourceRef::YouTube(VIDEO.to_owned()),
+                dir.local("a.mp3"),
+                SourceRef::Http("https://example.com/song.mp3".to_owned()),
+            ]
+        );
+        assert_eq!(names(&entries), [(None, None); 3]);
+    }
+
+    #[test]
+    fn parses_m3u_playlists() {
+        let dir = TestDir::new("m3u");
+        let data = format!(
+            "#EXTM3U\n#EXTINF:213,Rick Astley - Never Gonna Give You Up\n\
+             https://www.youtube.com/watch?v={VIDEO}\n#EXTINF:-1,Intro\na.mp3\nb.mp3\n"
+        );
+        let entries = dir.parser().parse_m3u(&data);
+        assert_eq!(
+            sources(&entries),
+            [
+                SourceRef::YouTube(VIDEO.to_owned()),
+                dir.local("a.mp3"),
+                dir.local("b.mp3"),
+            ]
+        );
+        assert_eq!(
+            names(&entries),
+            [
+                (Some("Rick Astley"), Some("Never Gonna Give You Up")),
+                (None, Some("Intro")),
+                (None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_pls_playlists() {
+        let dir = TestDir::new("pls");
+        let data = "[playlist]\nFile2=b.mp3\nTitle2=Second\nFile1=a.mp3\nTitle1=A - First\n\
+                    FileX=missing.mp3\nNumberOfEntries=2\nVersion=2\n";
+        let entries = dir.parser().parse_pls(data);
+        assert_eq!(sources(&entries), [dir.local("a.mp3"), dir.local("b.mp3")]);
+        assert_eq!(
+            names(&entries),
+            [(Some("A"), Some("First")), (None, Some("Second"))]
+        );
+    }
+
+    #[test]
+    fn parses_json_playlists() {
+        let dir = TestDir::new("json");
+        let data = format!(
+            r#"["{VIDEO}", {{"source": "a.mp3", "title": "First"}}, {{"source": "missing.mp3"}}]"#
+        );
+        let entries = dir.parser().parse_json(&data).unwrap();
+        assert_eq!(
+            sources(&entries),
+            [SourceRef::YouTube(VIDEO.to_owned()), dir.local("a.mp3")]
+        );
+        assert_eq!(names(&entries), [(None, None), (None, Some("First"))]);
+
+        assert!(dir.parser().parse_json("{}").is_err());
+    }
+
+    #[test]
+    fn expands_directories() {
+        let dir = TestDir::new("dir");
+        fs::write(dir.0.join("notes.txt"), b"").unwrap();
+        let entries = dir.parser().parse_plain(".\n");
+        assert_eq!(sources(&entries), [dir.local("a.mp3"), dir.local("b.mp3")]);
+    }
+
+    #[test]
+    fn detects_format_from_first_line() {
+        let dir = TestDir::new("detect");
+        let path = dir.0.join("list.txt");
+        fs::write(&path, "[playlist]\nFile1=a.mp3\n").unwrap();
+        assert_eq!(sources(&load(&path).unwrap()), [dir.local("a.mp3")]);
+
+        fs::write(&path, "#EXTM3U\n#EXTINF:1,Title\nb.mp3\n").unwrap();
+        let entries = load(&path).unwrap();
+        assert_eq!(sources(&entries), [dir.local("b.mp3")]);
+        assert_eq!(names(&entries), [(None, Some("Title"))]);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt::{self, Display};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use shared::misc;
+use shared::protocol::{self, SongSource};
+
+use crate::state::{DownloadProgress, Song};
+use crate::util;
+
+mod http;
+mod local;
+mod youtube;
+
+pub use local::is_audio_file;
+
+/// Local copy of the music library searched by the server.
+static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory in which songs of the music library of the server are looked up.
+pub fn set_library(dir: PathBuf) {
+    _ = LIBRARY.set(dir);
+}
+
+/// Returns the path of a file of the music library in the local copy of the library.
+fn library_file(relative: &Path) -> Result<PathBuf> {
+    match LIBRARY.get() {
+        Some(library) => Ok(library.join(relative)),
+        None => bail!(
+            "{} is part of the music library, which was not given",
+            relative.display()
+        ),
+    }
+}
+
+/// A place songs are fetched from.
+pub trait AudioSource: Sync {
+    /// Fetches the title, artist and thumbnail of a song.
+    fn fetch_info(&self, source: &SourceRef) -> Result<Song>;
+
+    /// Fetches the audio of a song and writes it to `output`. `progress` is called whenever the
+    /// progress is known to have changed. Fails early once `cancel` is set.
+    fn fetch_audio(
+        &self,
+        source: &SourceRef,
+        output: &Path,
+        progress: &dyn Fn(DownloadProgress),
+        cancel: &AtomicBool,
+    ) -> Result<()>;
+
+    /// Returns the path of the audio if it is played in place instead of being downloaded into
+    /// the cache.
+    fn local_path(&self, _source: &SourceRef) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Returns the audio source that handles the reference.
+pub fn get(source: &SourceRef) -> &'static dyn AudioSource {
+    match source {
+        SourceRef::YouTube(_) => &youtube::YouTubeSource,
+        SourceRef::Local(_) | SourceRef::Library(_) => &local::LocalSource,
+        SourceRef::Http(_) => &http::HttpSource,
+    }
+}
+
+/// Replaces a reference to a local directory by all audio files in it, recursively. Other
+/// references are returned as they are. Fails for local paths that are not valid UTF-8, as they
+/// cannot be written to the journal or the history.
+pub fn expand(source: SourceRef) -> Result<Vec<SourceRef>> {
+    match source {
+        SourceRef::Local(ref path) if path.to_str().is_none() => {
+            bail!("{} is not valid UTF-8", path.display())
+        }
+        SourceRef::Local(ref path) if path.is_dir() => {
+            let files = local::scan_directory(path)?;
+            log::info!("found {} audio files in {}", files.len(), path.display());
+            Ok(files.into_iter().map(SourceRef::Local).collect())
+        }
+        source => Ok(vec![source]),
+    }
+}
+
+/// Fetches the videos of a YouTube playlist URL. Returns `None` if the URL does not point to a
+/// playlist, URLs of a video in a playlist point to the video only.
+pub fn expand_playlist_url(url: &str) -> Option<Result<Vec<SourceRef>>> {
+    if youtube::video_id(url).is_some() {
+        return None;
+    }
+    let list = youtube::playlist_id(url)?;
+    let videos = youtube::playlist_videos(&list);
+    Some(videos.map(|ids| ids.into_iter().map(SourceRef::YouTube).collect()))
+}
+
+/// Reference to a song of one of the audio sources. Written as a YouTube video ID or URL, as
+/// `file:<path>` or an absolute path, as `library:<path>`, or as an HTTP URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum SourceRef {
+    /// A YouTube video ID.
+    YouTube(String),
+    Local(PathBuf),
+    /// A file of the music library of the server, relative to the local copy of the library.
+    Library(PathBuf),
+    Http(String),
+}
+
+impl SourceRef {
+    /// Converts the source of a song pushed by the server. Fails if the source does not match the
+    /// song ID, or if a library path points outside of the library.
+    pub fn from_push(song_id: &str, source: SongSource) -> Result<Self, String> {
+        let source = match source {
+            SongSource::YouTube => Self::YouTube(song_id.to_owned()),
+            SongSource::Library(path) => {
+                let path = PathBuf::from(path);
+                let is_inside = path.components().all(|c| matches!(c, Component::Normal(_)));
+                if path.as_os_str().is_empty() || !is_inside {
+                    return Err(format!("invalid library path {}", path.display()));
+                }
+                Self::Library(path)
+            }
+        };
+        match protocol::is_valid_song_id(song_id) && source.id() == song_id {
+            true => Ok(source),
+            false => Err(format!("song id {song_id:?} does not match {source}")),
+        }
+    }
+
+    /// Returns the ID of the song, which identifies it in the state, the cache and the protocol.
+    /// For YouTube videos, this is the video ID, for songs of the music library of the server, it
+    /// is the ID the server derived from the path.
+    pub fn id(&self) -> String {
+        match self {
+            Self::YouTube(id) => id.clone(),
+            Self::Library(path) => misc::hash_id(&path.to_string_lossy()),
+            other => misc::hash_id(&other.to_string()),
+        }
+    }
+
+    /// Returns the path of the file that is played for the song.
+    pub fn audio_location(&self) -> PathBuf {
+        get(self)
+            .local_path(self)
+            .unwrap_or_else(|| util::audio_cache_location(&self.id()))
+    }
+
+    /// Returns the URL of the thumbnail shown to guests. Only YouTube videos have one.
+    pub fn thumbnail_url(&self) -> Option<String> {
+        match self {
+            Self::YouTube(id) => Some(format!("https://i.ytimg.com/vi/{id}/maxresdefault.jpg")),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the audio is played in place, so it never has to be downloaded.
+    pub fn is_local(&self) -> bool {
+        get(self).local_path(self).is_some()
+    }
+}
+
+impl FromStr for SourceRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if protocol::is_valid_song_id(s) {
+            return Ok(Self::YouTube(s.to_owned()));
+        }
+        if let Some(path) = s
+            .strip_prefix("file://")
+            .or_else(|| s.strip_prefix("file:"))
+        {
+            return Ok(Self::Local(PathBuf::from(path)));
+        }
+        if let Some(path) = s.strip_prefix("library:") {
+            return Ok(Self::Library(PathBuf::from(path)));
+        }
+        if s.starts_with('/') {
+            return Ok(Self::Local(PathBuf::from(s)));
+        }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return match youtube::video_id(s) {
+                Some(id) => Ok(Self::YouTube(id)),
+                None => Ok(Self::Http(s.to_owned())),
+            };
+        }
+        Err(format!("invalid song reference {s:?}"))
+    }
+}
+
+impl Display for SourceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::YouTube(id) => write!(f, "{id}"),
+            Self::Local(path) => write!(f, "file:{}", path.display()),
+            Self::Library(path) => write!(f, "library:{}", path.display()),
+            Self::Http(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+impl From<SourceRef> for String {
+    fn from(source: SourceRef) -> Self {
+        source.to_string()
+    }
+}
+
+impl TryFrom<String> for SourceRef {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_pushed_sources() {
+        let source = SourceRef::from_push("dQw4w9WgXcQ", SongSource::YouTube).unwrap();
+        assert_eq!(source, SourceRef::YouTube("dQw4w9WgXcQ".to_owned()));
+
+        let id = misc::hash_id("Artist/Song.mp3");
+        let library = SongSource::Library("Artist/Song.mp3".to_owned());
+        let source = SourceRef::from_push(&id, library).unwrap();
+        assert_eq!(source, SourceRef::Library(PathBuf::from("Artist/Song.mp3")));
+        assert_eq!(source.id(), id);
+    }
+
+    #[test]
+    fn rejects_invalid_pushed_sources() {
+        assert!(SourceRef::from_push("invalid", SongSource::YouTube).is_err());
+
+        // the song ID has to match the path
+        let library = SongSource::Library("Artist/Song.mp3".to_owned());
+        assert!(SourceRef::from_push("dQw4w9WgXcQ", library).is_err());
+
+        // library paths may not leave the library
+        for path in ["/etc/passwd", "../Song.mp3", "Artist/../../Song.mp3", ""] {
+            let id = misc::hash_id(path);
+            let library = SongSource::Library(path.to_owned());
+            assert!(SourceRef::from_push(&id, library).is_err(), "{path}");
+        }
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let sources = [
+            SourceRef::YouTube("dQw4w9WgXcQ".to_owned()),
+            SourceRef::Local(PathBuf::from("/music/Song.mp3")),
+            SourceRef::Library(PathBuf::from("Artist/Song.mp3")),
+            SourceRef::Http("https://example.com/song.mp3".to_owned()),
+        ];
+        for source in sources {
+            assert_eq!(source.to_string().parse::<SourceRef>(), Ok(source));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_paths_that_are_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/music/S\xf6ng.mp3"));
+        assert!(expand(SourceRef::Local(path)).is_err());
+    }
+}
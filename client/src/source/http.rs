@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use reqwest::blocking::Client;
+
+use super::{AudioSource, SourceRef};
+use crate::state::{DownloadProgress, Song};
+
+/// Downloads audio files from arbitrary HTTP URLs. The name of the file is used as title and the
+/// host as artist.
+pub struct HttpSource;
+
+impl AudioSource for HttpSource {
+    fn fetch_info(&self, source: &SourceRef) -> Result<Song> {
+        let SourceRef::Http(url) = source else {
+            bail!("{source} is not an HTTP URL");
+        };
+
+        let without_query = url.split(['?', '#']).next().unwrap_or(url);
+        let rest = without_query
+            .split_once("://")
+            .map_or(without_query, |(_, rest)| rest);
+        let host = rest.split('/').next().unwrap_or(rest);
+        let name = rest.rsplit('/').next().unwrap_or(rest);
+        let title = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+
+        Ok(Song::new(
+            source.clone(),
+            title.to_owned(),
+            host.to_owned(),
+            Vec::new(),
+        ))
+    }
+
+    fn fetch_audio(
+        &self,
+        source: &SourceRef,
+        output: &Path,
+        progress: &dyn Fn(DownloadProgress),
+        cancel: &AtomicBool,
+    ) -> Result<()> {
+        let SourceRef::Http(url) = source else {
+            bail!("{source} is not an HTTP URL");
+        };
+
+        log::info!("downloading {url}");
+        let mut response = Client::builder()
+            .timeout(None)
+            .build()?
+            .get(url)
+            .send()?
+            .error_for_status()?;
+        let total = response.content_length();
+
+        let mut file = File::create(output)?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut downloaded = 0;
+        let mut last_report = Instant::now();
+        let started_at = Instant::now();
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                bail!("cancelled");
+            }
+
+            let len = response.read(&mut buffer)?;
+            if len == 0 {
+                return Ok(());
+            }
+            file.write_all(&buffer[..len])?;
+            downloaded += len as u64;
+
+            if let Some(total) = total
+                && last_report.elapsed() >= Duration::from_millis(250)
+            {
+                last_report = Instant::now();
+                let speed = downloaded as f64 / started_at.elapsed().as_secs_f64();
+                let remaining = total.saturating_sub(downloaded) as f64;
+                progress(DownloadProgress {
+                    fraction: (downloaded as f64 / total as f64).min(1.0) as f32,
+                    speed: Some(speed),
+                    eta: (speed > 0.0).then(|| Duration::from_secs_f64(remaining / speed)),
+                });
+            }
+        }
+    }
+}
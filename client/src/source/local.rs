@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+
+use anyhow::{bail, Result};
+use image::{ImageFormat, ImageReader};
+use serde::Deserialize;
+
+use super::{library_file, AudioSource, SourceRef};
+use crate::state::{DownloadProgress, Song};
+
+/// Extensions of the files picked up when scanning a directory.
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aiff", "alac", "flac", "m4a", "mp3", "oga", "ogg", "opus", "wav", "webm", "wma",
+];
+
+/// Names of image files used as cover art for all songs in their directory, if a song has no
+/// embedded cover art.
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+
+/// Plays audio files from the local file system in place, including the files of the local copy
+/// of the music library of the server. Title and artist are read from the tags of the file, or
+/// from its name if it is called `<artist> - <title>.<ext>`.
+pub struct LocalSource;
+
+impl AudioSource for LocalSource {
+    fn fetch_info(&self, source: &SourceRef) -> Result<Song> {
+        let path = &match source {
+            SourceRef::Local(path) => path.clone(),
+            SourceRef::Library(relative) => library_file(relative)?,
+            _ => bail!("{source} is not a local file"),
+        };
+        if !path.is_file() {
+            bail!("{} does not exist", path.display());
+        }
+
+        log::info!("reading song info of {}", path.display());
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (name_artist, name_title) = match stem.split_once(" - ") {
+            Some((artist, title)) => (artist.to_owned(), title.to_owned()),
+            None => ("Unknown Artist".to_owned(), stem.clone()),
+        };
+
+        let tags = read_tags(path).unwrap_or_else(|e| {
+            log::warn!("failed to read tags of {}: {e}", path.display());
+            HashMap::new()
+        });
+        let title = tags.get("title").cloned().unwrap_or(name_title);
+        let artist = tags.get("artist").cloned().unwrap_or(name_artist);
+
+        // the user interface shows a placeholder for songs without cover art
+        let thumbnail = read_cover_art(path).unwrap_or_else(|e| {
+            log::info!("no cover art for {}: {e}", path.display());
+            Vec::new()
+        });
+
+        Ok(Song::new(source.clone(), title, artist, thumbnail))
+    }
+
+    fn fetch_audio(
+        &self,
+        source: &SourceRef,
+        _output: &Path,
+        _progress: &dyn Fn(DownloadProgress),
+        _cancel: &AtomicBool,
+    ) -> Result<()> {
+        bail!("{source} is played in place and never downloaded")
+    }
+
+    fn local_path(&self, source: &SourceRef) -> Option<PathBuf> {
+        match source {
+            SourceRef::Local(path) => Some(path.clone()),
+            SourceRef::Library(relative) => library_file(relative).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns all audio files in a directory and its subdirectories, sorted by path. Hidden files
+/// and directories are skipped, as are paths that are not valid UTF-8.
+pub fn scan_directory(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let is_hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if is_hidden {
+                continue;
+            }
+            if path.to_str().is_none() {
+                log::warn!("skipping {}, its path is not valid UTF-8", path.display());
+                continue;
+            }
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns whether the path has the extension of an audio file.
+pub fn is_audio_file(path: &Path) -> bool {
+    has_extension(path, AUDIO_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext.as_str()))
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    format: ProbeFormat,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Reads the tags of an audio file with lowercase keys.
+fn read_tags(path: &Path) -> Result<HashMap<String, String>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format_tags", "-of", "json"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let probe = serde_json::from_slice::<ProbeOutput>(&output.stdout)?;
+    let tags = probe
+        .format
+        .tags
+        .into_iter()
+        .map(|(key, value)| (key.to_lowercase(), value))
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    Ok(tags)
+}
+
+/// Reads the cover art embedded in an audio file, or else the cover image next to it, as PNG.
+fn read_cover_art(path: &Path) -> Result<Vec<u8>> {
+    let image = match read_embedded_cover_art(path)? {
+        Some(image) => image,
+        None => {
+            let Some(cover) = find_cover_file(path.parent().unwrap_or(Path::new("."))) else {
+                bail!("neither embedded nor next to the file");
+            };
+            fs::read(cover)?
+        }
+    };
+
+    let mut thumbnail = Vec::new();
+    ImageReader::new(Cursor::new(image))
+        .with_guessed_format()?
+        .decode()?
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)?;
+    Ok(thumbnail)
+}
+
+fn read_embedded_cover_art(path: &Path) -> Result<Option<Vec<u8>>> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args([
+            "-an",
+            "-frames:v",
+            "1",
+            "-f",
+            "image2pipe",
+            "-c:v",
+            "png",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .output()?;
+
+    // ffmpeg fails if the file has no video stream, i.e. no cover art
+    match output.status.success() && !output.stdout.is_empty() {
+        true => Ok(Some(output.stdout)),
+        false => Ok(None),
+    }
+}
+
+fn find_cover_file(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .find(|path| {
+            let is_cover = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase())
+                .is_some_and(|stem| COVER_NAMES.contains(&stem.as_str()));
+            is_cover && has_extension(path, &["jpg", "jpeg", "png"])
+        })
+}
@@ -0,0 +1,277 @@
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use image::{ImageFormat, ImageReader};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use super::{AudioSource, SourceRef};
+use crate::state::{DownloadProgress, Song};
+
+/// Fetches song info from the oEmbed endpoint of YouTube and audio with yt-dlp.
+pub struct YouTubeSource;
+
+impl AudioSource for YouTubeSource {
+    fn fetch_info(&self, source: &SourceRef) -> Result<Song> {
+        let SourceRef::YouTube(id) = source else {
+            bail!("{source} is not a YouTube video");
+        };
+
+        log::info!("fetching song info for {id}");
+
+        let client = Client::new();
+        let request_url = format!(
+            "https://www.youtube.com/oembed?format=json&url=https://www.youtube.com/watch?v={id}"
+        );
+        let mut response = client
+            .get(request_url)
+            .send()?
+            .json::<YoutubeVideoResponse>()?;
+
+        if response.author_name.ends_with(" - Topic") {
+            response
+                .author_name
+                .truncate(response.author_name.len() - 8);
+        }
+        response.thumbnail_url = response
+            .thumbnail_url
+            .replace("hqdefault.jpg", "maxresdefault.jpg");
+
+        log::info!(
+            "got song info for {id}: {} / {}",
+            response.title,
+            response.author_name
+        );
+
+        log::info!("fetching thumbnail for {id} at {}", response.thumbnail_url);
+
+        let client = Client::new();
+        let orig_thumbnail = client.get(&response.thumbnail_url).send()?.bytes()?;
+
+        let mut thumbnail = Vec::new();
+        ImageReader::new(Cursor::new(orig_thumbnail))
+            .with_guessed_format()?
+            .decode()?
+            .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)?;
+
+        Ok(Song::new(
+            source.clone(),
+            response.title,
+            response.author_name,
+            thumbnail,
+        ))
+    }
+
+    fn fetch_audio(
+        &self,
+        source: &SourceRef,
+        output: &Path,
+        progress: &dyn Fn(DownloadProgress),
+        cancel: &AtomicBool,
+    ) -> Result<()> {
+        let SourceRef::YouTube(id) = source else {
+            bail!("{source} is not a YouTube video");
+        };
+
+        let mut command = Command::new("yt-dlp");
+        let command = command
+            .arg("--format")
+            .arg("bestaudio[ext=m4a]")
+            .arg("--extract-audio")
+            .arg("--newline")
+            .arg("--progress-template")
+            .arg(PROGRESS_TEMPLATE)
+            .arg("--output")
+            .arg(output)
+            .arg(format!("https://music.youtube.com/watch?v={id}"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        log::info!("executing {command:?}");
+        let mut child = command.spawn()?;
+
+        // the progress is read on a separate thread, so that waiting for output does not delay
+        // cancellation
+        let stdout = child.stdout.take().unwrap();
+        let (progress_tx, progress_rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+                if let Some(progress) = parse_progress(&line)
+                    && progress_tx.send(progress).is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        // stderr is drained while yt-dlp is running, as it blocks once the pipe is full
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr = thread::spawn(move || {
+            let mut output = String::new();
+            _ = stderr.read_to_string(&mut output);
+            output
+        });
+
+        loop {
+            progress_rx.try_iter().for_each(progress);
+
+            if cancel.load(Ordering::Relaxed) {
+                _ = child.kill();
+                _ = child.wait();
+                bail!("cancelled");
+            }
+
+            match child.try_wait()? {
+                Some(status) if status.success() => return Ok(()),
+                Some(status) => {
+                    let stderr = stderr.join().unwrap_or_default();
+                    bail!("yt-dlp failed with exit code {status}:\n{}", stderr.trim());
+                }
+                None => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+}
+
+/// Extracts the video ID from a YouTube URL.
+pub fn video_id(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/')?;
+
+    let id = match host.trim_start_matches("www.") {
+        "youtu.be" => path.split(['?', '&']).next()?,
+        "youtube.com" | "music.youtube.com" | "m.youtube.com" => path
+            .strip_prefix("watch?")?
+            .split('&')
+            .find_map(|param| param.strip_prefix("v="))?,
+        _ => return None,
+    };
+    shared::protocol::is_valid_song_id(id).then(|| id.to_owned())
+}
+
+/// Extracts the playlist ID from a YouTube URL.
+pub fn playlist_id(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/')?;
+
+    match host.trim_start_matches("www.") {
+        "youtube.com" | "music.youtube.com" | "m.youtube.com" => path
+            .split_once('?')?
+            .1
+            .split('&')
+            .find_map(|param| param.strip_prefix("list="))
+            .filter(|id| !id.is_empty())
+            .map(str::to_owned),
+        _ => None,
+    }
+}
+
+/// Lists the video IDs of a playlist with yt-dlp, without fetching the videos themselves.
+pub fn playlist_videos(list: &str) -> Result<Vec<String>> {
+    log::info!("fetching videos of playlist {list}");
+
+    let output = Command::new("yt-dlp")
+        .arg("--flat-playlist")
+        .arg("--print")
+        .arg("id")
+        .arg(format!("https://www.youtube.com/playlist?list={list}"))
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "yt-dlp failed with exit code {}:\n{}",
+            output.status,
+            stderr.trim()
+        );
+    }
+
+    let ids = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|id| shared::protocol::is_valid_song_id(id))
+        .map(str::to_owned)
+        .collect();
+    Ok(ids)
+}
+
+#[derive(Deserialize)]
+struct YoutubeVideoResponse {
+    author_name: String,
+    thumbnail_url: String,
+    title: String,
+}
+
+/// Makes yt-dlp print the progress as `schmu <downloaded> <total> <estimated total> <speed> <eta>`,
+/// where values that are not known are printed as `NA`.
+const PROGRESS_TEMPLATE: &str = "download:schmu %(progress.downloaded_bytes)s \
+    %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s";
+
+fn parse_progress(line: &str) -> Option<DownloadProgress> {
+    let mut values = line
+        .strip_prefix("schmu ")?
+        .split_whitespace()
+        .map(|value| value.parse::<f64>().ok());
+    let downloaded = values.next()??;
+    let total = values.next()?;
+    let estimate = values.next()?;
+    let speed = values.next()?;
+    let eta = values.next()?;
+
+    let total = total.or(estimate)?;
+    Some(DownloadProgress {
+        fraction: (downloaded / total).clamp(0.0, 1.0) as f32,
+        speed,
+        eta: eta.filter(|eta| *eta >= 0.0).map(Duration::from_secs_f64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress() {
+        let progress = parse_progress("schmu 1024 4096 NA 512.5 6").unwrap();
+        assert_eq!(progress.fraction, 0.25);
+        assert_eq!(progress.speed, Some(512.5));
+        assert_eq!(progress.eta, Some(Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn falls_back_to_estimated_size() {
+        let progress = parse_progress("schmu 1024 NA 2048.0 NA NA").unwrap();
+        assert_eq!(progress.fraction, 0.5);
+        assert_eq!(progress.speed, None);
+        assert_eq!(progress.eta, None);
+    }
+
+    #[test]
+    fn rejects_lines_without_progress() {
+        assert!(parse_progress("[download] Destination: foo.m4a").is_none());
+        assert!(parse_progress("schmu NA 4096 NA NA NA").is_none());
+        assert!(parse_progress("schmu 1024 NA NA NA NA").is_none());
+        assert!(parse_progress("schmu 1024 4096").is_none());
+    }
+
+    #[test]
+    fn clamps_fraction_and_ignores_negative_eta() {
+        let progress = parse_progress("schmu 5000 4096 NA NA -1").unwrap();
+        assert_eq!(progress.fraction, 1.0);
+        assert_eq!(progress.eta, None);
+    }
+}
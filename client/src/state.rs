@@ -1,10 +1,13 @@
 use std::{
-    collections::{vec_deque::Iter, VecDeque},
+    collections::{vec_deque::Iter, HashMap, VecDeque},
     sync::{Mutex, MutexGuard},
     time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
+use shared::protocol::{PlayerStatus, PlayingStatus, SongStatus};
+
+use crate::source::SourceRef;
 
 static STATE: Mutex<State> = Mutex::new(State::new());
 
@@ -14,25 +17,105 @@ pub fn get() -> MutexGuard<'static, State> {
 
 pub struct State {
     queue: VecDeque<Song>,
+    /// Song suggestions waiting for the host to approve them, only used in moderation mode.
+    pending_approval: VecDeque<Song>,
     fallback_queue: VecDeque<Song>,
+    /// Songs of the fallback playlists that were handed to the downloader but have not been
+    /// played yet, including the ones whose song info has not been downloaded yet.
+    fallback_playlist: Vec<SourceRef>,
+    /// Songs of the fallback playlists that have not been handed to the downloader yet.
+    upcoming_fallback: Vec<SourceRef>,
+    /// Position at which playback of a song restored from the journal is resumed.
+    resume: Option<(String, Duration)>,
+    /// Names of the fallback playlists that are active right now.
+    mood: Option<String>,
     playing: Option<PlayingSong>,
     connection: ConnectionState,
+    /// ID assigned by the server and the token with which it can be reclaimed after a restart.
+    session: Option<Session>,
+    max_songs_per_guest: usize,
+    moderated: bool,
 }
 
 impl State {
     const fn new() -> Self {
         Self {
             queue: VecDeque::new(),
+            pending_approval: VecDeque::new(),
             fallback_queue: VecDeque::new(),
+            fallback_playlist: Vec::new(),
+            upcoming_fallback: Vec::new(),
+            resume: None,
+            mood: None,
             playing: None,
             connection: ConnectionState::NotConnected,
+            session: None,
+            max_songs_per_guest: 0,
+            moderated: false,
         }
     }
 
+    /// Sets the maximum number of songs a listener may have in the queue. 0 disables the limit.
+    pub fn set_max_songs_per_guest(&mut self, max: usize) {
+        self.max_songs_per_guest = max;
+    }
+
+    /// Enables moderation mode, in which song suggestions have to be approved by the host before
+    /// they are queued.
+    pub fn set_moderated(&mut self, moderated: bool) {
+        self.moderated = moderated;
+    }
+
+    pub fn is_moderated(&self) -> bool {
+        self.moderated
+    }
+
+    /// Returns the IDs of all songs that are playing or queued.
+    pub fn song_ids(&self) -> Vec<String> {
+        self.playing
+            .iter()
+            .map(|playing| &playing.song)
+            .chain(&self.queue)
+            .chain(&self.pending_approval)
+            .chain(&self.fallback_queue)
+            .map(|song| song.id.clone())
+            .collect()
+    }
+
+    /// Returns the ID of the first song in playing order whose audio has not been downloaded yet.
+    pub fn next_song_to_download(&self) -> Option<String> {
+        self.queue
+            .iter()
+            .chain(&self.fallback_queue)
+            .find(|song| !song.downloaded)
+            .map(|song| song.id.clone())
+    }
+
+    pub fn set_mood(&mut self, mood: Option<String>) {
+        self.mood = mood;
+    }
+
+    pub fn mood(&self) -> Option<&str> {
+        self.mood.as_deref()
+    }
+
+    pub fn set_upcoming_fallback(&mut self, songs: Vec<SourceRef>) {
+        self.upcoming_fallback = songs;
+    }
+
+    /// Adds songs of the fallback playlists to the songs that have not been played yet.
+    pub fn extend_fallback_playlist(&mut self, songs: Vec<SourceRef>) {
+        self.fallback_playlist.extend(songs);
+    }
+
     pub fn queue(&self) -> Iter<'_, Song> {
         self.queue.iter()
     }
 
+    pub fn pending_approval(&self) -> Iter<'_, Song> {
+        self.pending_approval.iter()
+    }
+
     pub fn fallback_queue(&self) -> Iter<'_, Song> {
         self.fallback_queue.iter()
     }
@@ -45,29 +128,131 @@ impl State {
         !self.fallback_queue.is_empty()
     }
 
-    pub fn enqueue(&mut self, song: Song, is_fallback: bool) {
+    /// Adds a song to the queue. In moderation mode, song suggestions are added to the list of
+    /// songs pending approval instead. Returns whether the song was queued, i.e. whether its
+    /// audio should be downloaded.
+    pub fn enqueue(&mut self, song: Song, is_fallback: bool) -> bool {
         if let Some(ref playing) = self.playing
             && playing.song.id == song.id
         {
-            return;
+            return false;
         }
 
-        if self.queue.iter().any(|s| s.id == song.id) {
-            return;
+        if self.queue.iter().any(|s| s.id == song.id)
+            || self.pending_approval.iter().any(|s| s.id == song.id)
+        {
+            return false;
         }
 
-        let queue = match is_fallback {
-            false => &mut self.queue,
-            true => &mut self.fallback_queue,
-        };
+        if is_fallback {
+            // short fallback playlists may start the next round before the song is played
+            if self.fallback_queue.iter().any(|s| s.id == song.id) {
+                return false;
+            }
+            self.fallback_queue.push_back(song);
+            return true;
+        }
+
+        let songs_of_submitter = self
+            .queue
+            .iter()
+            .chain(&self.pending_approval)
+            .filter(|s| s.submitter == song.submitter)
+            .count();
+        if song.submitter.is_some()
+            && self.max_songs_per_guest > 0
+            && songs_of_submitter >= self.max_songs_per_guest
+        {
+            log::warn!(
+                "not queueing {}, its submitter has too many songs queued",
+                song.id
+            );
+            return false;
+        }
+
+        if self.moderated {
+            self.pending_approval.push_back(song);
+            return false;
+        }
+
+        self.insert_suggestion(song);
+        true
+    }
+
+    /// Moves the oldest song pending approval into the queue. Returns its source and whether its
+    /// audio still has to be downloaded.
+    pub fn approve_next(&mut self) -> Option<(SourceRef, bool)> {
+        let song = self.pending_approval.pop_front()?;
+        let approved = (song.source(), !song.downloaded);
+        self.insert_suggestion(song);
+        Some(approved)
+    }
 
-        queue.push_back(song);
+    /// Removes the oldest song pending approval.
+    pub fn reject_next(&mut self) -> Option<Song> {
+        self.pending_approval.pop_front()
+    }
+
+    /// Removes the first occurrence of a song from the songs of the fallback playlists that have
+    /// not been played yet, after it was played or dropped. Later rounds may contain it again.
+    pub fn remove_from_fallback_playlist(&mut self, id: &str) {
+        if let Some(index) = self.fallback_playlist.iter().position(|s| s.id() == id) {
+            self.fallback_playlist.remove(index);
+        }
+    }
+
+    fn insert_suggestion(&mut self, song: Song) {
+        // songs of different listeners are interleaved round-robin: the n-th queued song of a
+        // listener is placed behind the n-th queued songs of all other listeners
+        let round = self
+            .queue
+            .iter()
+            .filter(|s| s.submitter == song.submitter)
+            .count();
+        let mut rounds = HashMap::new();
+        let index = self
+            .queue
+            .iter()
+            .position(|queued| {
+                let count = rounds.entry(&queued.submitter).or_insert(0);
+                *count += 1;
+                *count > round + 1
+            })
+            .unwrap_or(self.queue.len());
+
+        self.queue.insert(index, song);
+    }
+
+    pub fn set_download_state(&mut self, id: &str, download: DownloadState) {
+        for song in self.songs_mut().filter(|song| song.id == id) {
+            song.download = download.clone();
+        }
+    }
+
+    /// Marks the download of a song as failed for good. Fallback songs are dropped instead, so
+    /// that the fallback queue is refilled with songs that can be played.
+    pub fn fail_download(&mut self, id: &str, attempts: usize) {
+        self.set_download_state(id, DownloadState::Failed { attempts });
+        if let Some(index) = self.fallback_queue.iter().position(|song| song.id == id) {
+            self.fallback_queue.remove(index);
+            self.remove_from_fallback_playlist(id);
+        }
+    }
+
+    fn songs_mut(&mut self) -> impl Iterator<Item = &mut Song> {
+        self.queue
+            .iter_mut()
+            .chain(&mut self.pending_approval)
+            .chain(&mut self.fallback_queue)
     }
 
     pub fn mark_downloaded(&mut self, id: &str) {
         if let Some(ref mut item) = self.queue.iter_mut().find(|item| item.id == id) {
             item.downloaded = true;
         }
+        if let Some(ref mut item) = self.pending_approval.iter_mut().find(|item| item.id == id) {
+            item.downloaded = true;
+        }
         if let Some(ref mut item) = self.fallback_queue.iter_mut().find(|item| item.id == id) {
             item.downloaded = true;
         }
@@ -84,45 +269,173 @@ impl State {
         }
     }
 
-    pub fn get_next_song(&mut self) -> Option<String> {
-        let song = match self.queue.iter().position(|item| item.downloaded) {
-            Some(index) => self.queue.remove(index).unwrap(),
+    pub fn get_next_song(&mut self) -> Option<SourceRef> {
+        let (song, is_fallback) = match self.queue.iter().position(|item| item.downloaded) {
+            Some(index) => (self.queue.remove(index).unwrap(), false),
             None => match self.fallback_queue.iter().position(|item| item.downloaded) {
-                Some(index) => self.fallback_queue.remove(index).unwrap(),
+                Some(index) => {
+                    let song = self.fallback_queue.remove(index).unwrap();
+                    self.remove_from_fallback_playlist(&song.id);
+                    (song, true)
+                }
                 None => {
                     self.playing = None;
                     return None;
                 }
             },
         };
-        let id = song.id.clone();
+        // a song restored from the journal is only resumed if it is the first song played again
+        if self
+            .resume
+            .as_ref()
+            .is_some_and(|(resume_id, _)| *resume_id != song.id)
+        {
+            self.resume = None;
+        }
+        let source = song.source();
         self.playing = Some(PlayingSong {
             song,
             total: Duration::from_secs(0),
             elapsed: Duration::from_secs(0),
+            is_fallback,
         });
-        Some(id)
+        Some(source)
     }
 
-    pub fn set_connected(&mut self, id: String) {
+    pub fn set_connected(&mut self, id: String, resume_token: String) {
+        self.session = Some(Session {
+            id: id.clone(),
+            resume_token,
+        });
         self.connection = ConnectionState::Connected { id };
     }
 
     pub fn set_connection_error(&mut self, msg: String) {
-        self.connection = ConnectionState::Error { msg }
+        self.connection = match &self.connection {
+            ConnectionState::Connected { id } | ConnectionState::Reconnecting { id, .. } => {
+                ConnectionState::Reconnecting {
+                    id: id.clone(),
+                    msg,
+                }
+            }
+            _ => ConnectionState::Error { msg },
+        }
     }
 
     pub fn connection_state(&self) -> &ConnectionState {
         &self.connection
     }
 
+    pub fn status(&self) -> PlayerStatus {
+        PlayerStatus {
+            playing: self.playing.as_ref().map(|playing| PlayingStatus {
+                song: playing.song.status(),
+                elapsed: playing.elapsed.as_secs(),
+                total: playing.total.as_secs(),
+                is_fallback: playing.is_fallback,
+            }),
+            queue: self.queue.iter().map(Song::status).collect(),
+            pending_approval: self.pending_approval.iter().map(Song::status).collect(),
+            fallback_queue_len: self.fallback_queue.len(),
+            recently_played: Vec::new(), // filled in from the history
+        }
+    }
+
+    /// Moves the song with the given ID one position up in the queue of song suggestions.
+    pub fn move_up_song(&mut self, id: &str) {
+        if let Some(index) = self.queue.iter().position(|song| song.id == id)
+            && index > 0
+        {
+            self.queue.swap(index, index - 1);
+        }
+    }
+
+    /// Returns the part of the state that is journaled to disk. The playing song is put in front
+    /// of its queue, so that it is played first after a restart.
+    pub fn snapshot(&self) -> Snapshot {
+        let entry = |song: &Song| SnapshotEntry {
+            source: song.source(),
+            submitter: song.submitter.clone(),
+        };
+
+        let mut queue = Vec::new();
+        let mut fallback_playlist = Vec::new();
+        let mut resume = None;
+        if let Some(ref playing) = self.playing {
+            match playing.is_fallback {
+                false => queue.push(entry(&playing.song)),
+                true => fallback_playlist.push(playing.song.source()),
+            }
+            resume = Some(Resume {
+                id: playing.song.id.clone(),
+                elapsed: playing.elapsed.as_secs(),
+            });
+        }
+
+        queue.extend(self.queue.iter().map(entry));
+        fallback_playlist.extend(self.fallback_queue.iter().map(Song::source));
+        for source in &self.fallback_playlist {
+            if !fallback_playlist.contains(source) {
+                fallback_playlist.push(source.clone());
+            }
+        }
+        // the upcoming songs are a different part of the rounds, so they are not deduplicated
+        fallback_playlist.extend(self.upcoming_fallback.iter().cloned());
+
+        Snapshot {
+            queue,
+            pending_approval: self.pending_approval.iter().map(entry).collect(),
+            fallback_playlist,
+            resume,
+            session: self.session.clone(),
+        }
+    }
+
+    /// Adds a song restored from the journal, keeping the order of the journal.
+    pub fn restore(&mut self, song: Song, pending_approval: bool) {
+        match pending_approval {
+            false => self.queue.push_back(song),
+            true => self.pending_approval.push_back(song),
+        }
+    }
+
+    /// Keeps the session of the previous run until the server assigns an ID.
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.session = session;
+    }
+
+    pub fn set_resume(&mut self, resume: Option<Resume>) {
+        self.resume = resume.map(|resume| (resume.id, Duration::from_secs(resume.elapsed)));
+    }
+
+    /// Returns the position at which playback of the song should start.
+    pub fn take_resume(&mut self, id: &str) -> Option<Duration> {
+        match self.resume {
+            Some((ref resume_id, _)) if resume_id == id => self.resume.take().map(|(_, at)| at),
+            _ => None,
+        }
+    }
+
+    // index = 1 -> queue[0]
+    // index = queue.len() + 1 -> fallback_queue[0]
+    pub fn song_at(&self, index: usize) -> Option<&Song> {
+        if index <= self.queue.len() {
+            self.queue.get(index.checked_sub(1)?)
+        } else {
+            self.fallback_queue.get(index - self.queue.len() - 1)
+        }
+    }
+
     // index = 1 -> queue[0]
     // index = queue.len() + 1 -> fallback_queue[0]
     pub fn delete_song(&mut self, index: usize) {
         if index <= self.queue.len() {
             self.queue.remove(index - 1);
         } else {
-            self.fallback_queue.remove(index - self.queue.len() - 1);
+            let song = self.fallback_queue.remove(index - self.queue.len() - 1);
+            if let Some(song) = song {
+                self.remove_from_fallback_playlist(&song.id);
+            }
         }
     }
 
@@ -177,16 +490,245 @@ pub struct Song {
     pub artist: String,
     pub downloaded: bool,
     pub thumbnail: Vec<u8>,
+    /// Guest token of the listener who submitted the song, `None` for fallback songs.
+    #[serde(skip)]
+    pub submitter: Option<String>,
+    #[serde(skip)]
+    pub download: DownloadState,
+    /// Missing in song info cached before songs could come from other sources than YouTube.
+    #[serde(default)]
+    source: Option<SourceRef>,
+}
+
+/// State of the audio download of a song that has not been downloaded yet.
+#[derive(Clone, Default)]
+pub enum DownloadState {
+    #[default]
+    Queued,
+    Downloading(DownloadProgress),
+    Failed {
+        attempts: usize,
+    },
+}
+
+#[derive(Clone)]
+pub struct DownloadProgress {
+    /// Downloaded fraction between 0 and 1.
+    pub fraction: f32,
+    /// Download speed in bytes per second.
+    pub speed: Option<f64>,
+    /// Estimated time until the download is finished.
+    pub eta: Option<Duration>,
+}
+
+impl Song {
+    pub fn new(source: SourceRef, title: String, artist: String, thumbnail: Vec<u8>) -> Self {
+        Self {
+            id: source.id(),
+            title,
+            artist,
+            // local files are played in place
+            downloaded: source.is_local(),
+            thumbnail,
+            submitter: None,
+            download: DownloadState::Queued,
+            source: Some(source),
+        }
+    }
+
+    pub fn source(&self) -> SourceRef {
+        self.source
+            .clone()
+            .unwrap_or_else(|| SourceRef::YouTube(self.id.clone()))
+    }
+
+    pub fn status(&self) -> SongStatus {
+        SongStatus {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            downloaded: self.downloaded,
+            thumbnail: self.source().thumbnail_url(),
+        }
+    }
+}
+
+/// The queues and the playing song as journaled to disk.
+#[derive(Default, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub queue: Vec<SnapshotEntry>,
+    pub pending_approval: Vec<SnapshotEntry>,
+    /// Songs of the fallback playlist that have not been played yet, in order.
+    pub fallback_playlist: Vec<SourceRef>,
+    pub resume: Option<Resume>,
+    pub session: Option<Session>,
+}
+
+impl Snapshot {
+    pub fn song_ids(&self) -> Vec<String> {
+        self.queue
+            .iter()
+            .chain(&self.pending_approval)
+            .map(|entry| &entry.source)
+            .chain(&self.fallback_playlist)
+            .map(SourceRef::id)
+            .collect()
+    }
+}
+
+#[derive(PartialEq, Deserialize, Serialize)]
+pub struct SnapshotEntry {
+    #[serde(alias = "id")]
+    pub source: SourceRef,
+    pub submitter: Option<String>,
+}
+
+/// Song that was playing when the snapshot was taken.
+#[derive(PartialEq, Deserialize, Serialize)]
+pub struct Resume {
+    pub id: String,
+    /// Elapsed time in seconds.
+    pub elapsed: u64,
+}
+
+/// Session of the client on the server.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct Session {
+    pub id: String,
+    /// Token that proves to the server that the ID is reclaimed by the same client.
+    pub resume_token: String,
 }
 
 pub struct PlayingSong {
     pub song: Song,
     pub total: Duration,
     pub elapsed: Duration,
+    pub is_fallback: bool,
 }
 
 pub enum ConnectionState {
     NotConnected,
     Connected { id: String },
+    Reconnecting { id: String, msg: String },
     Error { msg: String },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(id: &str, submitter: &str) -> Song {
+        let mut song = Song::new(
+            SourceRef::YouTube(id.repeat(11)),
+            id.to_owned(),
+            "artist".to_owned(),
+            Vec::new(),
+        );
+        song.submitter = Some(submitter.to_owned());
+        song
+    }
+
+    fn titles(songs: Iter<'_, Song>) -> Vec<&str> {
+        songs.map(|song| song.title.as_str()).collect()
+    }
+
+    #[test]
+    fn suggestions_are_interleaved_per_guest() {
+        let mut state = State::new();
+        for (id, submitter) in [("a", "x"), ("b", "x"), ("c", "x"), ("d", "y"), ("e", "z")] {
+            assert!(state.enqueue(suggestion(id, submitter), false));
+        }
+        assert_eq!(titles(state.queue()), ["a", "d", "e", "b", "c"]);
+
+        assert!(state.enqueue(suggestion("f", "y"), false));
+        assert_eq!(titles(state.queue()), ["a", "d", "e", "b", "f", "c"]);
+    }
+
+    #[test]
+    fn later_suggestions_go_behind_earlier_ones_of_the_same_round() {
+        let mut state = State::new();
+        for (id, submitter) in [("a", "x"), ("b", "y"), ("c", "x"), ("d", "z"), ("e", "y")] {
+            assert!(state.enqueue(suggestion(id, submitter), false));
+        }
+        assert_eq!(titles(state.queue()), ["a", "b", "d", "c", "e"]);
+    }
+
+    #[test]
+    fn suggestions_are_limited_per_guest() {
+        let mut state = State::new();
+        state.set_max_songs_per_guest(2);
+        assert!(state.enqueue(suggestion("a", "x"), false));
+        assert!(state.enqueue(suggestion("b", "x"), false));
+        assert!(!state.enqueue(suggestion("c", "x"), false));
+        assert!(state.enqueue(suggestion("d", "y"), false));
+        assert_eq!(titles(state.queue()), ["a", "d", "b"]);
+    }
+
+    #[test]
+    fn duplicate_suggestions_are_ignored() {
+        let mut state = State::new();
+        assert!(state.enqueue(suggestion("a", "x"), false));
+        assert!(!state.enqueue(suggestion("a", "y"), false));
+        assert_eq!(titles(state.queue()), ["a"]);
+    }
+
+    #[test]
+    fn approved_suggestions_are_interleaved() {
+        let mut state = State::new();
+        state.set_moderated(true);
+        for (id, submitter) in [("a", "x"), ("b", "x"), ("c", "y")] {
+            assert!(!state.enqueue(suggestion(id, submitter), false));
+        }
+        assert_eq!(titles(state.pending_approval()), ["a", "b", "c"]);
+
+        while state.approve_next().is_some() {}
+        assert_eq!(titles(state.queue()), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn only_the_first_song_played_is_resumed() {
+        let mut state = State::new();
+        for id in ["a", "b"] {
+            let mut song = suggestion(id, "x");
+            song.downloaded = true;
+            state.restore(song, false);
+        }
+        state.set_resume(Some(Resume {
+            id: "b".repeat(11),
+            elapsed: 42,
+        }));
+
+        assert_eq!(
+            state.get_next_song(),
+            Some(SourceRef::YouTube("a".repeat(11)))
+        );
+        assert_eq!(state.take_resume(&"a".repeat(11)), None);
+        assert_eq!(
+            state.get_next_song(),
+            Some(SourceRef::YouTube("b".repeat(11)))
+        );
+        assert_eq!(state.take_resume(&"b".repeat(11)), None);
+    }
+
+    #[test]
+    fn failed_fallback_songs_are_dropped() {
+        let mut state = State::new();
+        let songs = ["a", "b", "c"].map(|id| suggestion(id, "x"));
+        let ids = songs.each_ref().map(|song| song.id.clone());
+        state.extend_fallback_playlist(songs.iter().map(Song::source).collect());
+        for song in songs {
+            assert!(state.enqueue(song, true));
+        }
+        assert!(state.enqueue(suggestion("a", "y"), false));
+
+        for id in &ids {
+            state.fail_download(id, 3);
+        }
+        assert_eq!(state.fallback_queue().count(), 0);
+        assert!(state.fallback_playlist.is_empty());
+        assert!(matches!(
+            state.queue().next().unwrap().download,
+            DownloadState::Failed { attempts: 3 }
+        ));
+    }
+}
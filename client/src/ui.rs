@@ -1,15 +1,18 @@
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt::Write;
 use std::io::Cursor;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use image::{ImageFormat, Luma};
 use qrcode::QrCode;
 use raylib::prelude::*;
 use shared::misc::CallOnDrop;
 
-use crate::state::{self, ConnectionState};
+use crate::history;
+use crate::state::{self, ConnectionState, DownloadState, Song};
 use crate::util::{self, Event};
 
 pub struct UI {
@@ -132,6 +135,7 @@ fn ui(
 
     let mut qr_contrast: u8 = 105;
     let mut qr_size: u8 = 6;
+    let mut show_history = false;
 
     /* user interface *****************************************************************************/
 
@@ -169,6 +173,15 @@ fn ui(
                     state::get().delete_song(edit_index);
                     queue_edit_mode = None;
                 }
+                Some(KeyboardKey::KEY_B) => {
+                    let mut state = state::get();
+                    if let Some(song) = state.song_at(edit_index) {
+                        let id = song.id.clone();
+                        event_tx.send(Event::BlockSong { id }).unwrap();
+                        state.delete_song(edit_index);
+                    }
+                    queue_edit_mode = None;
+                }
                 Some(KeyboardKey::KEY_J) => {
                     let new_index = state::get().move_down(edit_index);
                     queue_edit_mode = Some(new_index);
@@ -196,6 +209,9 @@ fn ui(
                 Some(KeyboardKey::KEY_W) => qr_contrast = qr_contrast.saturating_add(10),
                 Some(KeyboardKey::KEY_A) => qr_size = qr_size.saturating_sub(1).max(1),
                 Some(KeyboardKey::KEY_S) => qr_size = qr_size.saturating_add(1),
+                Some(KeyboardKey::KEY_H) => show_history = !show_history,
+                Some(KeyboardKey::KEY_ENTER) => event_tx.send(Event::ApproveSong).unwrap(),
+                Some(KeyboardKey::KEY_BACKSPACE) => event_tx.send(Event::RejectSong).unwrap(),
                 Some(KeyboardKey::KEY_ONE) => queue_edit_mode = Some(1),
                 Some(KeyboardKey::KEY_TWO) => queue_edit_mode = Some(2),
                 Some(KeyboardKey::KEY_THREE) => queue_edit_mode = Some(3),
@@ -304,33 +320,15 @@ fn ui(
                 Color::GAINSBORO,
             );
 
-            if !song.downloaded {
-                let rotation = ((time % 1.0) * 360.0) as f32;
-                let texture_rect = rrect(0, 0, spinner.width(), spinner.height());
-                let output_rect = rrect(190, y + 46.0, 20, 20);
-                let origin = rvec2(10, 10);
-                d.draw_texture_pro(
-                    &spinner,
-                    texture_rect,
-                    output_rect,
-                    origin,
-                    rotation,
-                    Color::GRAY,
-                );
-            }
-
-            let offset = match song.downloaded {
-                true => 0.0,
-                false => 24.0,
-            };
+            let (offset, info, color) = draw_download_state(song, y, time, &spinner, &mut d);
 
             d.draw_text_ex(
                 &font_bold,
-                &song.artist,
+                &format!("{}{info}", song.artist),
                 rvec2(181.0 + offset, y + 36.0),
                 FONT_SIZE_BOLD as f32,
                 0.0,
-                Color::GRAY,
+                color,
             );
 
             if let Some(edit_index) = queue_edit_mode
@@ -367,15 +365,19 @@ fn ui(
         } else if state.has_fallback_queue() && y as i32 <= screen_height - 220 {
             /* fallback queue *********************************************************************/
 
+            let mood = match state.mood() {
+                Some(mood) => format!(" - Mood: {mood}"),
+                None => String::new(),
+            };
             let msg = match state.has_song_suggestions() {
-                true => "Fallback Queue (will be played when suggestions run out):",
-                false => "Fallback Queue:",
+                true => format!("Fallback Queue{mood} (will be played when suggestions run out):"),
+                false => format!("Fallback Queue{mood}:"),
             };
 
             y += 32.0;
             d.draw_text_ex(
                 &font_bold,
-                msg,
+                &msg,
                 rvec2(100, y),
                 FONT_SIZE_BOLD as f32,
                 0.0,
@@ -400,33 +402,15 @@ fn ui(
                     Color::GAINSBORO,
                 );
 
-                if !song.downloaded {
-                    let rotation = ((time % 1.0) * 360.0) as f32;
-                    let texture_rect = rrect(0, 0, spinner.width(), spinner.height());
-                    let output_rect = rrect(190, y + 46.0, 20, 20);
-                    let origin = rvec2(10, 10);
-                    d.draw_texture_pro(
-                        &spinner,
-                        texture_rect,
-                        output_rect,
-                        origin,
-                        rotation,
-                        Color::GRAY,
-                    );
-                }
-
-                let offset = match song.downloaded {
-                    true => 0.0,
-                    false => 24.0,
-                };
+                let (offset, info, color) = draw_download_state(song, y, time, &spinner, &mut d);
 
                 d.draw_text_ex(
                     &font_bold,
-                    &song.artist,
+                    &format!("{}{info}", song.artist),
                     rvec2(181.0 + offset, y + 36.0),
                     FONT_SIZE_BOLD as f32,
                     0.0,
-                    Color::GRAY,
+                    color,
                 );
 
                 if let Some(edit_index) = queue_edit_mode
@@ -469,6 +453,107 @@ fn ui(
             queue_edit_mode = None;
         }
 
+        /* pending approval ***********************************************************************/
+
+        if state.is_moderated() {
+            let x = screen_width - 620;
+            let mut y = 100.0;
+            let mut pending = state.pending_approval();
+
+            d.draw_text_ex(
+                &font_bold,
+                &format!("Awaiting approval ({})", pending.len()),
+                rvec2(x, y),
+                FONT_SIZE_BOLD as f32,
+                0.0,
+                Color::DIMGRAY,
+            );
+            y += 32.0;
+
+            if let Some(song) = pending.next() {
+                let thumbnail = thumbnails.get(&song.id);
+                draw_thumbnail(x, y as i32, 64, thumbnail, &mut d);
+
+                d.draw_text_ex(
+                    &font_regular,
+                    &song.title,
+                    rvec2(x + 80, y + 4.0),
+                    FONT_SIZE_REGULAR as f32,
+                    0.0,
+                    Color::GAINSBORO,
+                );
+
+                d.draw_text_ex(
+                    &font_bold,
+                    &song.artist,
+                    rvec2(x + 80, y + 36.0),
+                    FONT_SIZE_BOLD as f32,
+                    0.0,
+                    Color::GRAY,
+                );
+                y += 80.0;
+
+                d.draw_text_ex(
+                    &font_bold,
+                    "Enter: approve, Backspace: reject",
+                    rvec2(x, y),
+                    FONT_SIZE_BOLD as f32,
+                    0.0,
+                    Color::DIMGRAY,
+                );
+            }
+        }
+
+        /* history ********************************************************************************/
+
+        if show_history {
+            let x = screen_width - 620;
+            let mut y = if state.is_moderated() { 300.0 } else { 100.0 };
+
+            d.draw_text_ex(
+                &font_bold,
+                "Recently played",
+                rvec2(x, y),
+                FONT_SIZE_BOLD as f32,
+                0.0,
+                Color::DIMGRAY,
+            );
+            y += 32.0;
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            for entry in history::get().recent(8) {
+                d.draw_text_ex(
+                    &font_regular,
+                    &entry.title,
+                    rvec2(x, y),
+                    FONT_SIZE_REGULAR as f32,
+                    0.0,
+                    Color::GAINSBORO,
+                );
+
+                let minutes = now.saturating_sub(entry.played_at) / 60;
+                let mut info = format!("{} - {minutes} min ago", entry.artist);
+                if entry.skipped {
+                    info.push_str(", skipped");
+                }
+                if entry.is_fallback {
+                    info.push_str(", fallback");
+                }
+                d.draw_text_ex(
+                    &font_bold,
+                    &info,
+                    rvec2(x + 1, y + 32.0),
+                    FONT_SIZE_BOLD as f32,
+                    0.0,
+                    Color::GRAY,
+                );
+                y += 72.0;
+            }
+        }
+
         /* connection status **********************************************************************/
 
         match state.connection_state() {
@@ -516,6 +601,37 @@ fn ui(
                     qr_color,
                 );
             }
+            ConnectionState::Reconnecting { msg, .. } => {
+                // the server keeps the id reserved while reconnecting, so the qr code stays valid
+                let qr_color = Color::new(qr_contrast, qr_contrast, qr_contrast, 255);
+
+                let msg = format!("reconnecting: {msg}");
+                let text_width = font_bold.measure_text(&msg, FONT_SIZE_BOLD as f32, 0.0).x as i32;
+                let x = screen_width - text_width - 20;
+                let y = screen_height - FONT_SIZE_BOLD - 20;
+                d.draw_text_ex(
+                    &font_bold,
+                    &msg,
+                    rvec2(x, y),
+                    FONT_SIZE_BOLD as f32,
+                    0.0,
+                    Color::MAROON,
+                );
+
+                if let Some(qr) = server_qrcode.as_ref() {
+                    let size = 29 * (qr_size as i32);
+                    let x = screen_width - size - 20;
+                    let y = screen_height - size - 55;
+                    d.draw_texture_pro(
+                        qr,
+                        rrect(0, 0, qr.width(), qr.height()),
+                        rrect(x, y, size, size),
+                        rvec2(0, 0),
+                        0.0,
+                        qr_color,
+                    );
+                }
+            }
             ConnectionState::Error { msg } => {
                 let msg = format!("error: {msg}");
                 let text_width = font_bold.measure_text(&msg, FONT_SIZE_BOLD as f32, 0.0).x as i32;
@@ -560,8 +676,13 @@ impl ThumbnailStore {
             .map(|playing| &playing.song)
             .into_iter()
             .chain(state.queue())
+            .chain(state.pending_approval())
             .chain(state.fallback_queue())
         {
+            // songs from local files and HTTP URLs have no thumbnail and use the default
+            if song.thumbnail.is_empty() {
+                continue;
+            }
             if let Entry::Vacant(entry) = self.thumbnails.entry(song.id.to_owned()) {
                 let image = Image::load_image_from_mem(".png", &song.thumbnail).unwrap();
                 let mut texture = rl.load_texture_from_image(thread, &image).unwrap();
@@ -578,6 +699,61 @@ impl ThumbnailStore {
     }
 }
 
+/// Draws the state of the audio download of a song in the queue. Returns the offset of the artist,
+/// the text to show after the artist and the color of both.
+fn draw_download_state(
+    song: &Song,
+    y: f32,
+    time: f64,
+    spinner: &Texture2D,
+    draw: &mut RaylibDrawHandle<'_>,
+) -> (f32, String, Color) {
+    if song.downloaded {
+        return (0.0, String::new(), Color::GRAY);
+    }
+
+    if let DownloadState::Failed { attempts } = song.download {
+        let info = format!(" - download failed after {attempts} attempts");
+        return (0.0, info, Color::MAROON);
+    }
+
+    let rotation = ((time % 1.0) * 360.0) as f32;
+    let texture_rect = rrect(0, 0, spinner.width(), spinner.height());
+    let output_rect = rrect(190, y + 46.0, 20, 20);
+    let origin = rvec2(10, 10);
+    draw.draw_texture_pro(
+        spinner,
+        texture_rect,
+        output_rect,
+        origin,
+        rotation,
+        Color::GRAY,
+    );
+
+    let DownloadState::Downloading(ref progress) = song.download else {
+        return (24.0, String::new(), Color::GRAY);
+    };
+
+    // progress bar below the thumbnail
+    draw.draw_rectangle(100, y as i32 + 66, 64, 3, Color::new(40, 40, 40, 255));
+    draw.draw_rectangle(
+        100,
+        y as i32 + 66,
+        (64.0 * progress.fraction) as i32,
+        3,
+        Color::STEELBLUE,
+    );
+
+    let mut info = format!(" - {:.0}%", progress.fraction * 100.0);
+    if let Some(speed) = progress.speed {
+        _ = write!(info, " - {:.1} MB/s", speed / (1024.0 * 1024.0));
+    }
+    if let Some(eta) = progress.eta {
+        _ = write!(info, " - {}s left", eta.as_secs());
+    }
+    (24.0, info, Color::GRAY)
+}
+
 fn draw_thumbnail(x: i32, y: i32, size: i32, texture: &Texture2D, draw: &mut RaylibDrawHandle<'_>) {
     let min_side = i32::min(texture.width(), texture.height());
     let offset_x = (texture.width() - min_side) / 2;
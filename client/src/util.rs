@@ -1,18 +1,45 @@
 use std::fmt::Write;
 use std::path::PathBuf;
 
+use crate::source::SourceRef;
+
 pub fn audio_cache_location(id: &str) -> PathBuf {
     let mut cache = dirs::cache_dir().unwrap();
     cache.push(format!("schmu/{id}.m4a"));
     cache
 }
 
+/// Location the audio is downloaded to before it is moved into the cache.
+pub fn audio_download_location(id: &str) -> PathBuf {
+    let mut cache = dirs::cache_dir().unwrap();
+    cache.push(format!("schmu/{id}.tmp.m4a"));
+    cache
+}
+
 pub fn song_info_cache_location(id: &str) -> PathBuf {
     let mut cache = dirs::cache_dir().unwrap();
     cache.push(format!("schmu/{id}.json"));
     cache
 }
 
+pub fn cache_index_location() -> PathBuf {
+    let mut cache = dirs::cache_dir().unwrap();
+    cache.push("schmu/index.json");
+    cache
+}
+
+pub fn journal_location() -> PathBuf {
+    let mut cache = dirs::cache_dir().unwrap();
+    cache.push("schmu/state.json");
+    cache
+}
+
+pub fn history_location() -> PathBuf {
+    let mut data = dirs::data_dir().unwrap();
+    data.push("schmu/history.jsonl");
+    data
+}
+
 pub fn submission_url(id: &str, server_address: &str, server_port: u16) -> String {
     let mut s = String::new();
     match server_port {
@@ -26,10 +53,28 @@ pub fn submission_url(id: &str, server_address: &str, server_port: u16) -> Strin
 }
 
 pub enum Event {
-    ServerHello { id: String },
-    ConnError { msg: String },
-    ServerClose,
-    Push { song_id: String },
+    ServerHello {
+        id: String,
+        resume_token: String,
+    },
+    ConnError {
+        msg: String,
+    },
+    Push {
+        source: SourceRef,
+        submitter: Option<String>,
+    },
+    MoveUp {
+        song_id: String,
+    },
+    Skip {
+        song_id: String,
+    },
+    BlockSong {
+        id: String,
+    },
+    ApproveSong,
+    RejectSong,
     UIQuit,
     NextSong,
     TogglePause,
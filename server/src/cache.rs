@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::search::Song;
+
+static CACHE: OnceLock<Mutex<SearchCache>> = OnceLock::new();
+
+pub fn get() -> MutexGuard<'static, SearchCache> {
+    CACHE
+        .get_or_init(|| Mutex::new(SearchCache::from_config()))
+        .lock()
+        .unwrap()
+}
+
+/// In-memory cache of search results. Entries expire after the configured TTL, and the least
+/// recently used entry is evicted when the cache is full.
+pub struct SearchCache {
+    entries: HashMap<String, Entry>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+struct Entry {
+    songs: Vec<Song>,
+    inserted_at: Instant,
+    used_at: Instant,
+}
+
+impl SearchCache {
+    fn from_config() -> Self {
+        let config = config::get();
+        Self {
+            entries: HashMap::new(),
+            capacity: config.search_cache_size,
+            ttl: config.search_cache_ttl,
+        }
+    }
+
+    pub fn get(&mut self, query: &str) -> Option<Vec<Song>> {
+        let key = normalize(query);
+        let entry = self.entries.get_mut(&key)?;
+        if entry.inserted_at.elapsed() >= self.ttl {
+            self.entries.remove(&key);
+            return None;
+        }
+        entry.used_at = Instant::now();
+        Some(entry.songs.clone())
+    }
+
+    /// Returns a song with the given ID from any of the cached search results.
+    pub fn find(&self, id: &str) -> Option<Song> {
+        self.entries
+            .values()
+            .filter(|entry| entry.inserted_at.elapsed() < self.ttl)
+            .flat_map(|entry| &entry.songs)
+            .find(|song| song.id == id)
+            .cloned()
+    }
+
+    pub fn insert(&mut self, query: &str, songs: Vec<Song>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, entry| entry.inserted_at.elapsed() < ttl);
+
+        let key = normalize(query);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(key, _)| key.clone());
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+
+        let now = Instant::now();
+        self.entries.insert(
+            key,
+            Entry {
+                songs,
+                inserted_at: now,
+                used_at: now,
+            },
+        );
+    }
+}
+
+/// Normalizes a search query so that queries that only differ in case or whitespace share a
+/// cache entry.
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize) -> SearchCache {
+        SearchCache {
+            entries: HashMap::new(),
+            capacity,
+            ttl: Duration::from_secs(60),
+        }
+    }
+
+    fn song(id: &str) -> Song {
+        Song {
+            id: id.to_owned(),
+            title: format!("title of {id}"),
+            artist: format!("artist of {id}"),
+            album: None,
+            duration: None,
+            thumbnail: None,
+            explicit: false,
+            live: false,
+        }
+    }
+
+    fn ids(songs: Option<Vec<Song>>) -> Option<Vec<String>> {
+        songs.map(|songs| songs.into_iter().map(|song| song.id).collect())
+    }
+
+    /// Pretends that the entry of `query` was inserted and last used `ago`.
+    fn backdate(cache: &mut SearchCache, query: &str, ago: Duration) {
+        let entry = cache.entries.get_mut(&normalize(query)).unwrap();
+        entry.inserted_at -= ago;
+        entry.used_at -= ago;
+    }
+
+    #[test]
+    fn queries_are_normalized() {
+        let mut cache = cache(10);
+        cache.insert("Rick  Astley", vec![song("aaaaaaaaaaa")]);
+        assert_eq!(
+            ids(cache.get(" rick astley ")),
+            Some(vec!["aaaaaaaaaaa".to_owned()])
+        );
+        assert!(cache.get("rick").is_none());
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut cache = cache(10);
+        cache.insert("a", vec![song("aaaaaaaaaaa")]);
+        cache.insert("b", vec![song("bbbbbbbbbbb")]);
+        backdate(&mut cache, "a", Duration::from_secs(60));
+
+        assert!(cache.find("aaaaaaaaaaa").is_none());
+        assert!(cache.find("bbbbbbbbbbb").is_some());
+        assert!(cache.get("a").is_none());
+        assert!(!cache.entries.contains_key("a"));
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = cache(2);
+        cache.insert("a", vec![song("aaaaaaaaaaa")]);
+        cache.insert("b", vec![song("bbbbbbbbbbb")]);
+        backdate(&mut cache, "a", Duration::from_secs(2));
+        backdate(&mut cache, "b", Duration::from_secs(1));
+
+        // using "a" makes "b" the least recently used entry
+        assert!(cache.get("a").is_some());
+        cache.insert("c", vec![song("ccccccccccc")]);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn expired_entries_are_removed_before_evicting() {
+        let mut cache = cache(2);
+        cache.insert("a", vec![song("aaaaaaaaaaa")]);
+        cache.insert("b", vec![song("bbbbbbbbbbb")]);
+        backdate(&mut cache, "a", Duration::from_secs(1));
+        backdate(&mut cache, "b", Duration::from_secs(60));
+
+        cache.insert("c", vec![song("ccccccccccc")]);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn replacing_an_entry_evicts_nothing() {
+        let mut cache = cache(2);
+        cache.insert("a", vec![song("aaaaaaaaaaa")]);
+        cache.insert("b", vec![song("bbbbbbbbbbb")]);
+        cache.insert("a", vec![song("ccccccccccc")]);
+        assert_eq!(ids(cache.get("a")), Some(vec!["ccccccccccc".to_owned()]));
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_cache() {
+        let mut cache = cache(0);
+        cache.insert("a", vec![song("aaaaaaaaaaa")]);
+        assert!(cache.get("a").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::env;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Server configuration, read from environment variables on first access.
+pub struct Config {
+    /// Port the webserver listens on (`SCHMU_SERVER_PORT`).
+    pub port: u16,
+    /// How long the ID of a disconnected client stays reserved and submissions for it are
+    /// buffered (`SCHMU_SERVER_OFFLINE_WINDOW`, in seconds).
+    pub offline_window: Duration,
+    /// Whether the IP address of a client is taken from the `X-Forwarded-For` header. Should only
+    /// be enabled behind a reverse proxy (`SCHMU_SERVER_TRUST_PROXY`).
+    pub trust_proxy: bool,
+    /// Maximum number of submissions per minute from a single IP address
+    /// (`SCHMU_SERVER_SUBMIT_LIMIT_IP`, 0 disables the limit).
+    pub submit_limit_ip: u32,
+    /// Maximum number of submissions per minute to a single session
+    /// (`SCHMU_SERVER_SUBMIT_LIMIT_SESSION`, 0 disables the limit).
+    pub submit_limit_session: u32,
+    /// Maximum number of searches per minute from a single IP address
+    /// (`SCHMU_SERVER_SEARCH_LIMIT_IP`, 0 disables the limit).
+    pub search_limit_ip: u32,
+    /// Maximum number of searches per minute for a single session
+    /// (`SCHMU_SERVER_SEARCH_LIMIT_SESSION`, 0 disables the limit).
+    pub search_limit_session: u32,
+    /// Maximum number of searches that are sent to the search backend at the same time
+    /// (`SCHMU_SERVER_MAX_CONCURRENT_SEARCHES`).
+    pub max_concurrent_searches: usize,
+    /// Maximum number of search queries whose results are cached
+    /// (`SCHMU_SERVER_SEARCH_CACHE_SIZE`, 0 disables the cache).
+    pub search_cache_size: usize,
+    /// How long search results are cached (`SCHMU_SERVER_SEARCH_CACHE_TTL`, in seconds).
+    pub search_cache_ttl: Duration,
+    /// Where guests search for songs (`SCHMU_SERVER_SEARCH_BACKEND`, one of `ytmusic`, `library`
+    /// and `fixture`).
+    pub search_backend: SearchBackendKind,
+    /// Directory of audio files searched by the `library` backend (`SCHMU_SERVER_LIBRARY_PATH`).
+    pub library_path: PathBuf,
+    /// JSON file with the songs searched by the `fixture` backend (`SCHMU_SERVER_FIXTURE_PATH`).
+    pub fixture_path: PathBuf,
+}
+
+pub enum SearchBackendKind {
+    YtMusic,
+    Library,
+    Fixture,
+}
+
+impl FromStr for SearchBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ytmusic" => Ok(Self::YtMusic),
+            "library" => Ok(Self::Library),
+            "fixture" => Ok(Self::Fixture),
+            _ => Err(format!("unknown search backend {s:?}")),
+        }
+    }
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Self {
+            port: env_or("SCHMU_SERVER_PORT", shared::consts::SERVER_PORT_SERVER),
+            offline_window: Duration::from_secs(env_or("SCHMU_SERVER_OFFLINE_WINDOW", 30 * 60)),
+            trust_proxy: env_or("SCHMU_SERVER_TRUST_PROXY", false),
+            submit_limit_ip: env_or("SCHMU_SERVER_SUBMIT_LIMIT_IP", 10),
+            submit_limit_session: env_or("SCHMU_SERVER_SUBMIT_LIMIT_SESSION", 60),
+            search_limit_ip: env_or("SCHMU_SERVER_SEARCH_LIMIT_IP", 30),
+            search_limit_session: env_or("SCHMU_SERVER_SEARCH_LIMIT_SESSION", 300),
+            max_concurrent_searches: env_or("SCHMU_SERVER_MAX_CONCURRENT_SEARCHES", 4),
+            search_cache_size: env_or("SCHMU_SERVER_SEARCH_CACHE_SIZE", 1000),
+            search_cache_ttl: Duration::from_secs(env_or("SCHMU_SERVER_SEARCH_CACHE_TTL", 60 * 60)),
+            search_backend: env_or("SCHMU_SERVER_SEARCH_BACKEND", SearchBackendKind::YtMusic),
+            library_path: env_or("SCHMU_SERVER_LIBRARY_PATH", PathBuf::from("./library")),
+            fixture_path: env_or("SCHMU_SERVER_FIXTURE_PATH", PathBuf::from("./fixture.json")),
+        }
+    }
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::from_env)
+}
+
+fn env_or<T>(name: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid value for {name}: {e}")),
+        Err(_) => default,
+    }
+}
@@ -1,23 +1,71 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
 use rand::Rng;
+use serde::Serialize;
+use shared::filter::SongFilter;
+use shared::protocol::{PlayerStatus, ServerMessage, SessionConfig, SongSource, SongStatus};
 use tokio::sync::{
-    mpsc::{channel, Receiver, Sender},
-    Mutex, MutexGuard,
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch, Mutex, MutexGuard,
 };
 
+use crate::submissions::Submissions;
+use crate::votes::{VoteResult, Votes};
+use crate::{config, connections};
+
 static CONNECTIONS: Mutex<Connections> = Mutex::const_new(Connections::new());
 
+/// Maximum number of submissions buffered for a disconnected client.
+const MAX_PENDING: usize = 64;
+
 pub struct Connections {
     connections: Vec<Connection>,
+    next_generation: u64,
 }
 
 impl Connections {
     const fn new() -> Self {
         Self {
             connections: Vec::new(),
+            next_generation: 0,
         }
     }
 
-    pub fn register(&mut self, id: Option<String>) -> (String, Receiver<String>) {
+    pub fn register(&mut self, id: Option<String>, resume_token: Option<String>) -> Registration {
+        self.remove_expired();
+
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        let (sender, receiver) = unbounded_channel();
+
+        // reclaim the id of a previous connection if the client proves that it owns it. the old
+        // connection might not have noticed that it is dead yet, so it is simply taken over.
+        if let (Some(id), Some(token)) = (&id, &resume_token) {
+            if let Some(c) = self
+                .connections
+                .iter_mut()
+                .find(|c| &c.id == id && &c.resume_token == token)
+            {
+                if !c.pending.is_empty() {
+                    log::info!("flushing {} pending songs to {id}", c.pending.len());
+                }
+                for msg in c.pending.drain(..) {
+                    _ = sender.send(msg);
+                }
+                c.queue = Some(sender);
+                c.generation = generation;
+                c.disconnected_at = None;
+                c.status.send_modify(|status| status.online = true);
+                return Registration {
+                    id: c.id.clone(),
+                    resume_token: c.resume_token.clone(),
+                    generation,
+                    receiver,
+                };
+            }
+        }
+
         let mut id = match id {
             Some(id) => id,
             None => generate_id(),
@@ -27,34 +75,329 @@ impl Connections {
             id = generate_id();
         }
 
-        let (sender, receiver) = channel(64);
+        let resume_token = generate_token();
         self.connections.push(Connection {
             id: id.clone(),
-            queue: sender,
+            resume_token: resume_token.clone(),
+            generation,
+            queue: Some(sender),
+            pending: VecDeque::new(),
+            disconnected_at: None,
+            config: SessionConfig::default(),
+            filter: SongFilter::default(),
+            votes: Votes::default(),
+            submissions: Submissions::default(),
+            status: watch::Sender::new(SessionStatus {
+                online: true,
+                player: PlayerStatus::default(),
+                upvotes: HashMap::new(),
+                skip_votes: 0,
+                upvote_threshold: 0,
+                skip_threshold: 0,
+            }),
         });
-        (id, receiver)
+        Registration {
+            id,
+            resume_token,
+            generation,
+            receiver,
+        }
     }
 
-    pub fn unregister(&mut self, id: &str) {
-        if let Some(index) = self.connections.iter().position(|c| c.id == id) {
-            _ = self.connections.remove(index);
+    /// Marks the connection as disconnected. The ID stays reserved for the configured offline
+    /// window so that the client can reclaim it, and `undelivered` songs are kept until then. If
+    /// the connection has been taken over by a newer one in the meantime, the undelivered
+    /// messages are handed over to it instead.
+    fn unregister(&mut self, id: &str, generation: u64, undelivered: Vec<ServerMessage>) {
+        let Some(c) = self.connections.iter_mut().find(|c| c.id == id) else {
+            return;
+        };
+
+        if c.generation == generation {
+            c.queue = None;
+            c.disconnected_at = Some(Instant::now());
+            c.status.send_modify(|status| status.online = false);
+        }
+
+        for msg in undelivered {
+            match (&c.queue, msg) {
+                (Some(queue), msg) => _ = queue.send(msg),
+                (None, msg @ ServerMessage::Push { .. }) => c.pending.push_back(msg),
+                (None, _) => (),
+            }
         }
     }
 
-    pub fn exists(&self, id: &str) -> bool {
+    pub fn exists(&mut self, id: &str) -> bool {
+        self.remove_expired();
         self.connections.iter().any(|c| c.id == id)
     }
 
-    pub async fn submit(&mut self, id: &str, song: &str) {
-        if let Some(c) = self.connections.iter_mut().find(|c| c.id == id) {
-            _ = c.queue.send(song.to_owned()).await;
+    pub fn config(&mut self, id: &str) -> Option<SessionConfig> {
+        self.remove_expired();
+        self.connections
+            .iter()
+            .find(|c| c.id == id)
+            .map(|c| c.config.clone())
+    }
+
+    pub fn filter(&mut self, id: &str) -> Option<SongFilter> {
+        self.remove_expired();
+        self.connections
+            .iter()
+            .find(|c| c.id == id)
+            .map(|c| c.filter.clone())
+    }
+
+    pub fn update_filter(&mut self, id: &str, generation: u64, filter: SongFilter) {
+        if let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == id && c.generation == generation)
+        {
+            c.filter = filter;
+        }
+    }
+
+    pub fn update_config(&mut self, id: &str, generation: u64, config: SessionConfig) {
+        if let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == id && c.generation == generation)
+        {
+            c.status.send_modify(|status| {
+                status.upvote_threshold = config.upvote_threshold;
+                status.skip_threshold = config.skip_threshold;
+            });
+            c.config = config;
+        }
+    }
+
+    pub fn update_status(&mut self, id: &str, generation: u64, player: PlayerStatus) {
+        if let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == id && c.generation == generation)
+        {
+            c.votes.retain(&player);
+            c.submissions.retain(&player);
+            c.status.send_modify(|status| status.player = player);
+            c.publish_votes();
+        }
+    }
+
+    pub fn reject(&mut self, id: &str, generation: u64, song: SongStatus) {
+        if let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == id && c.generation == generation)
+        {
+            c.submissions.reject(song);
+        }
+    }
+
+    /// Returns the songs of the guest that were recently rejected by the host of the session.
+    pub fn rejected(&mut self, id: &str, guest: &str) -> Option<Vec<SongStatus>> {
+        self.connections
+            .iter_mut()
+            .find(|c| c.id == id)
+            .map(|c| c.submissions.rejected(guest))
+    }
+
+    pub fn upvote(&mut self, id: &str, song_id: &str, guest: &str) -> VoteResult {
+        let Some(c) = self.connections.iter_mut().find(|c| c.id == id) else {
+            return VoteResult::InvalidSession;
+        };
+        let Some(ref queue) = c.queue else {
+            return VoteResult::Offline;
+        };
+
+        let queued = c
+            .status
+            .borrow()
+            .player
+            .queue
+            .iter()
+            .any(|s| s.id == song_id);
+        if !queued {
+            return VoteResult::InvalidSong;
+        }
+
+        let result = c.votes.upvote(song_id, guest, c.config.upvote_threshold);
+        if let VoteResult::ThresholdReached = result {
+            log::info!("moving up {song_id} on {id} by vote");
+            let song_id = song_id.to_owned();
+            _ = queue.send(ServerMessage::MoveUp { song_id });
+        }
+        c.publish_votes();
+        result
+    }
+
+    pub fn vote_skip(&mut self, id: &str, guest: &str) -> VoteResult {
+        let Some(c) = self.connections.iter_mut().find(|c| c.id == id) else {
+            return VoteResult::InvalidSession;
+        };
+        let Some(ref queue) = c.queue else {
+            return VoteResult::Offline;
+        };
+
+        let playing = c
+            .status
+            .borrow()
+            .player
+            .playing
+            .as_ref()
+            .map(|p| p.song.id.clone());
+        let Some(song_id) = playing else {
+            return VoteResult::InvalidSong;
+        };
+
+        let result = c.votes.skip(&song_id, guest, c.config.skip_threshold);
+        if let VoteResult::ThresholdReached = result {
+            log::info!("skipping {song_id} on {id} by vote");
+            _ = queue.send(ServerMessage::Skip { song_id });
+        }
+        c.publish_votes();
+        result
+    }
+
+    /// Returns a receiver for the status of the player with the given ID, which is updated
+    /// whenever the player reports a new status or goes offline.
+    pub fn subscribe(&mut self, id: &str) -> Option<watch::Receiver<SessionStatus>> {
+        self.remove_expired();
+        self.connections
+            .iter()
+            .find(|c| c.id == id)
+            .map(|c| c.status.subscribe())
+    }
+
+    pub fn submit(
+        &mut self,
+        id: &str,
+        song: &str,
+        source: SongSource,
+        guest: &str,
+    ) -> SubmitResult {
+        self.remove_expired();
+
+        let Some(c) = self.connections.iter_mut().find(|c| c.id == id) else {
+            return SubmitResult::InvalidSession;
+        };
+
+        let limit = c.config.max_songs_per_guest as usize;
+        if limit > 0 && c.submissions.count(guest) >= limit {
+            return SubmitResult::GuestLimitReached;
+        }
+
+        let msg = ServerMessage::Push {
+            song_id: song.to_owned(),
+            source,
+            submitter: Some(guest.to_owned()),
+        };
+
+        let msg = match c.queue {
+            Some(ref queue) => match queue.send(msg) {
+                Ok(()) => {
+                    c.submissions.add(song, guest);
+                    return SubmitResult::Delivered;
+                }
+                Err(e) => e.0,
+            },
+            None => msg,
+        };
+
+        if c.pending.len() >= MAX_PENDING {
+            return SubmitResult::QueueFull;
         }
+
+        log::info!("{id} is offline, buffering {song}");
+        c.pending.push_back(msg);
+        c.submissions.add(song, guest);
+        SubmitResult::Queued
+    }
+
+    fn remove_expired(&mut self) {
+        let offline_window = config::get().offline_window;
+        self.connections.retain(|c| match c.disconnected_at {
+            Some(disconnected_at) if disconnected_at.elapsed() >= offline_window => {
+                if !c.pending.is_empty() {
+                    log::warn!("dropping {} pending songs for {}", c.pending.len(), c.id);
+                }
+                false
+            }
+            _ => true,
+        });
     }
 }
 
 pub struct Connection {
     id: String,
-    queue: Sender<String>,
+    resume_token: String,
+    generation: u64,
+    queue: Option<UnboundedSender<ServerMessage>>,
+    pending: VecDeque<ServerMessage>,
+    disconnected_at: Option<Instant>,
+    config: SessionConfig,
+    filter: SongFilter,
+    votes: Votes,
+    submissions: Submissions,
+    status: watch::Sender<SessionStatus>,
+}
+
+impl Connection {
+    fn publish_votes(&self) {
+        self.status.send_modify(|status| {
+            status.upvotes = self.votes.upvote_counts();
+            status.skip_votes = self.votes.skip_count();
+        });
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct SessionStatus {
+    pub online: bool,
+    #[serde(flatten)]
+    pub player: PlayerStatus,
+    /// Number of upvotes per queued song.
+    pub upvotes: HashMap<String, usize>,
+    pub skip_votes: usize,
+    pub upvote_threshold: u32,
+    pub skip_threshold: u32,
+}
+
+pub enum SubmitResult {
+    Delivered,
+    Queued,
+    QueueFull,
+    GuestLimitReached,
+    InvalidSession,
+}
+
+/// A registered connection. The connection is unregistered when this is dropped, and songs that
+/// were not yet taken out of `receiver` are buffered until the client reconnects.
+pub struct Registration {
+    pub id: String,
+    pub resume_token: String,
+    pub generation: u64,
+    pub receiver: UnboundedReceiver<ServerMessage>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.receiver.close();
+        let mut undelivered = Vec::new();
+        while let Ok(msg) = self.receiver.try_recv() {
+            undelivered.push(msg);
+        }
+
+        let id = self.id.clone();
+        let generation = self.generation;
+        tokio::spawn(async move {
+            connections::get()
+                .await
+                .unregister(&id, generation, undelivered)
+        });
+    }
 }
 
 pub async fn get() -> MutexGuard<'static, Connections> {
@@ -64,11 +407,20 @@ pub async fn get() -> MutexGuard<'static, Connections> {
 fn generate_id() -> String {
     const N: usize = 6;
     const CHARSET: &[u8] = b"abcdeghkmnpqrswxyzACEFGHLMNPRSTWY34679";
+    generate_string(N, CHARSET)
+}
+
+pub fn generate_token() -> String {
+    const N: usize = 32;
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    generate_string(N, CHARSET)
+}
 
-    let mut s = String::with_capacity(N);
+fn generate_string(n: usize, charset: &[u8]) -> String {
+    let mut s = String::with_capacity(n);
     let mut rng = rand::thread_rng();
-    for _ in 0..N {
-        let char = CHARSET[rng.gen_range(0..CHARSET.len())];
+    for _ in 0..n {
+        let char = charset[rng.gen_range(0..charset.len())];
         s.push(char as char);
     }
 
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use futures_util::future::{self, BoxFuture};
+use futures_util::FutureExt;
+
+use crate::search::{self, SearchBackend, Song};
+
+/// Searches a fixed list of songs read from a JSON file. Meant for running the server offline and
+/// for tests. The file must contain an array of objects with `id`, `title` and `artist` fields.
+pub struct FixtureBackend {
+    songs: Vec<Song>,
+}
+
+impl FixtureBackend {
+    pub fn new(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let songs = serde_json::from_slice(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(Self { songs })
+    }
+}
+
+impl SearchBackend for FixtureBackend {
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Vec<Song>>> {
+        let songs = self
+            .songs
+            .iter()
+            .filter(|song| search::matches(song, query))
+            .cloned()
+            .collect();
+        future::ready(Ok(songs)).boxed()
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<Song>>> {
+        let song = self.songs.iter().find(|song| song.id == id).cloned();
+        future::ready(Ok(song)).boxed()
+    }
+}
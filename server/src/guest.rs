@@ -0,0 +1,41 @@
+use axum::http::header::{COOKIE, SET_COOKIE};
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::connections;
+
+const COOKIE_NAME: &str = "schmu_guest";
+
+/// Returns the guest token stored in the cookies of the request, if there is a valid one.
+pub fn from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_NAME)
+        .map(|(_, token)| token.to_owned())
+        .filter(|token| is_valid_token(token))
+}
+
+/// Returns the guest token of the request. If the guest does not have a token yet, a new one is
+/// generated, and the returned headers contain a `Set-Cookie` header that should be added to the
+/// response.
+pub fn get_or_create(headers: &HeaderMap) -> (String, HeaderMap) {
+    let mut response_headers = HeaderMap::new();
+    let token = match from_headers(headers) {
+        Some(token) => token,
+        None => {
+            let token = connections::generate_token();
+            let cookie = format!("{COOKIE_NAME}={token}; Path=/; Max-Age=31536000; SameSite=Lax");
+            let cookie = HeaderValue::from_str(&cookie).expect("cookie is valid header value");
+            response_headers.insert(SET_COOKIE, cookie);
+            token
+        }
+    };
+    (token, response_headers)
+}
+
+fn is_valid_token(token: &str) -> bool {
+    token.len() == 32 && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
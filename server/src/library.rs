@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use futures_util::future::{self, BoxFuture};
+use futures_util::FutureExt;
+use shared::misc;
+use shared::protocol::SongSource;
+
+use crate::search::{self, SearchBackend, Song};
+
+const EXTENSIONS: &[&str] = &["flac", "m4a", "mp3", "ogg", "opus", "wav"];
+const MAX_RESULTS: usize = 20;
+
+/// Searches a directory of audio files, which is indexed once on startup. Files are expected to
+/// be named `<artist> - <title>.<ext>`. If the name does not contain an artist, the name of the
+/// containing directory is used instead. The client plays the songs from its own copy of the
+/// library, so they are pushed with their path relative to the library directory.
+pub struct LibraryBackend {
+    songs: Vec<Song>,
+    /// Paths of the songs relative to the library directory by song ID.
+    paths: HashMap<String, String>,
+}
+
+impl LibraryBackend {
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut library = Self {
+            songs: Vec::new(),
+            paths: HashMap::new(),
+        };
+        library
+            .index(path, path)
+            .with_context(|| format!("failed to index {}", path.display()))?;
+        log::info!("indexed {} songs", library.songs.len());
+        Ok(library)
+    }
+
+    fn index(&mut self, root: &Path, dir: &Path) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.index(root, &path)?;
+                continue;
+            }
+
+            let is_audio = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_audio {
+                continue;
+            }
+
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                log::warn!("skipping {}, the name is not valid utf-8", path.display());
+                continue;
+            };
+
+            let (artist, title) = match stem.split_once(" - ") {
+                Some((artist, title)) => (artist.to_owned(), title.to_owned()),
+                None => {
+                    let artist = path
+                        .parent()
+                        .filter(|parent| *parent != root)
+                        .and_then(|parent| parent.file_name())
+                        .and_then(|name| name.to_str())
+                        .unwrap_or("Unknown Artist");
+                    (artist.to_owned(), stem.to_owned())
+                }
+            };
+
+            let relative = path.strip_prefix(root)?.to_string_lossy().into_owned();
+            let id = misc::hash_id(&relative);
+            self.paths.insert(id.clone(), relative);
+            self.songs.push(Song {
+                id,
+                title,
+                artist,
+                album: None,
+                duration: None,
+                thumbnail: None,
+                explicit: false,
+                live: false,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl SearchBackend for LibraryBackend {
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Vec<Song>>> {
+        let songs = self
+            .songs
+            .iter()
+            .filter(|song| search::matches(song, query))
+            .take(MAX_RESULTS)
+            .cloned()
+            .collect();
+        future::ready(Ok(songs)).boxed()
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<Song>>> {
+        let song = self.songs.iter().find(|song| song.id == id).cloned();
+        future::ready(Ok(song)).boxed()
+    }
+
+    fn source(&self, id: &str) -> SongSource {
+        match self.paths.get(id) {
+            Some(path) => SongSource::Library(path.clone()),
+            None => SongSource::YouTube,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn songs_are_pushed_with_their_path() {
+        let root = env::temp_dir().join(format!("schmu-library-{}", process::id()));
+        fs::create_dir_all(root.join("Queen")).unwrap();
+        for file in [
+            "Queen/Bohemian Rhapsody.mp3",
+            "Rick Astley - Together Forever.flac",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        fs::write(root.join("notes.txt"), b"").unwrap();
+        let library = LibraryBackend::new(&root);
+        _ = fs::remove_dir_all(&root);
+        let library = library.unwrap();
+
+        let songs = library.search("queen bohemian").await.unwrap();
+        let [song] = &songs[..] else {
+            panic!("expected exactly one song, found {}", songs.len());
+        };
+        assert_eq!(song.title, "Bohemian Rhapsody");
+        assert_eq!(song.id, misc::hash_id("Queen/Bohemian Rhapsody.mp3"));
+        assert_eq!(
+            library.source(&song.id),
+            SongSource::Library("Queen/Bohemian Rhapsody.mp3".to_owned())
+        );
+
+        let songs = library.search("astley").await.unwrap();
+        let [song] = &songs[..] else {
+            panic!("expected exactly one song, found {}", songs.len());
+        };
+        assert_eq!(
+            (song.artist.as_str(), song.title.as_str()),
+            ("Rick Astley", "Together Forever")
+        );
+        assert!(library.search("notes").await.unwrap().is_empty());
+    }
+}
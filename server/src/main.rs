@@ -1,6 +1,15 @@
+mod cache;
+mod config;
 mod connections;
+mod fixture;
+mod guest;
+mod library;
+mod ratelimit;
+mod search;
 mod server;
 mod socket;
+mod submissions;
+mod votes;
 mod ytapi;
 
 #[tokio::main]
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use tokio::sync::Semaphore;
+
+use crate::config;
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Rate limits of the routes that can be abused to flood a player or to get the YouTube Music
+/// cookie banned.
+pub struct Limits {
+    submit_ip: Mutex<RateLimiter>,
+    submit_session: Mutex<RateLimiter>,
+    search_ip: Mutex<RateLimiter>,
+    search_session: Mutex<RateLimiter>,
+    /// Limits the number of searches that are sent to the search backend at the same time.
+    pub searches: Semaphore,
+}
+
+impl Limits {
+    fn from_config() -> Self {
+        let config = config::get();
+        Self {
+            submit_ip: Mutex::new(RateLimiter::new(config.submit_limit_ip)),
+            submit_session: Mutex::new(RateLimiter::new(config.submit_limit_session)),
+            search_ip: Mutex::new(RateLimiter::new(config.search_limit_ip)),
+            search_session: Mutex::new(RateLimiter::new(config.search_limit_session)),
+            searches: Semaphore::new(config.max_concurrent_searches.max(1)),
+        }
+    }
+
+    /// Counts a submission. If a limit is exceeded, the time after which the client may try again
+    /// is returned.
+    pub fn submit(&self, ip: IpAddr, session: &str) -> Result<(), Duration> {
+        self.submit_ip.lock().unwrap().check(&ip.to_string())?;
+        self.submit_session.lock().unwrap().check(session)
+    }
+
+    /// Counts a search. If a limit is exceeded, the time after which the client may try again is
+    /// returned.
+    pub fn search(&self, ip: IpAddr, session: &str) -> Result<(), Duration> {
+        self.search_ip.lock().unwrap().check(&ip.to_string())?;
+        self.search_session.lock().unwrap().check(session)
+    }
+}
+
+pub fn get() -> &'static Limits {
+    LIMITS.get_or_init(Limits::from_config)
+}
+
+/// Returns the IP address of the client. Behind a reverse proxy, the address is taken from the
+/// `X-Forwarded-For` header if the server is configured to trust it.
+pub fn client_ip(addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    if config::get().trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    addr.ip()
+}
+
+/// Token bucket rate limiter. Every key gets a bucket of `per_minute` tokens that is refilled
+/// continuously over the course of a minute.
+struct RateLimiter {
+    per_minute: u32,
+    buckets: HashMap<String, Bucket>,
+    last_cleanup: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: HashMap::new(),
+            last_cleanup: Instant::now(),
+        }
+    }
+
+    /// Takes a token from the bucket of `key`. If the bucket is empty, the time until the next
+    /// token becomes available is returned. A limit of 0 disables rate limiting.
+    fn check(&mut self, key: &str) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+
+        self.cleanup();
+
+        let capacity = self.per_minute as f64;
+        let rate = capacity / 60.0;
+        let bucket = self.buckets.entry(key.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: Instant::now(),
+        });
+
+        let elapsed = bucket.updated_at.elapsed().as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated_at = Instant::now();
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Forgets the buckets that have been refilled completely, as they are equivalent to new
+    /// ones.
+    fn cleanup(&mut self) {
+        if self.last_cleanup.elapsed() < Self::CLEANUP_INTERVAL {
+            return;
+        }
+        self.last_cleanup = Instant::now();
+
+        let full_after = Duration::from_secs(60);
+        self.buckets
+            .retain(|_, bucket| bucket.updated_at.elapsed() < full_after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretends that the bucket of `key` was last touched `ago`.
+    fn backdate(limiter: &mut RateLimiter, key: &str, ago: Duration) {
+        let bucket = limiter.buckets.get_mut(key).unwrap();
+        bucket.updated_at -= ago;
+    }
+
+    #[test]
+    fn allows_up_to_the_limit() {
+        let mut limiter = RateLimiter::new(3);
+        for _ in 0..3 {
+            assert!(limiter.check("a").is_ok());
+        }
+        let retry_after = limiter.check("a").unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(20));
+    }
+
+    #[test]
+    fn keys_have_separate_buckets() {
+        let mut limiter = RateLimiter::new(1);
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("b").is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut limiter = RateLimiter::new(6);
+        for _ in 0..6 {
+            assert!(limiter.check("a").is_ok());
+        }
+        assert!(limiter.check("a").is_err());
+
+        // one token every 10 seconds
+        backdate(&mut limiter, "a", Duration::from_secs(21));
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+
+        // never more than the limit
+        backdate(&mut limiter, "a", Duration::from_secs(120));
+        for _ in 0..6 {
+            assert!(limiter.check("a").is_ok());
+        }
+        assert!(limiter.check("a").is_err());
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let mut limiter = RateLimiter::new(0);
+        for _ in 0..1000 {
+            assert!(limiter.check("a").is_ok());
+        }
+    }
+
+    #[test]
+    fn cleanup_forgets_full_buckets() {
+        let mut limiter = RateLimiter::new(1);
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("b").is_ok());
+        backdate(&mut limiter, "a", Duration::from_secs(61));
+        limiter.last_cleanup -= RateLimiter::CLEANUP_INTERVAL;
+
+        limiter.cleanup();
+        assert!(!limiter.buckets.contains_key("a"));
+        assert!(limiter.buckets.contains_key("b"));
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::{self, Display};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use shared::filter::SongFilter;
+use shared::protocol::SongSource;
+
+use crate::cache;
+use crate::config::{self, SearchBackendKind};
+use crate::fixture::FixtureBackend;
+use crate::library::LibraryBackend;
+use crate::ytapi::YtMusicBackend;
+
+static BACKEND: OnceLock<Box<dyn SearchBackend>> = OnceLock::new();
+
+/// A source of songs that guests can search for on the submission page.
+pub trait SearchBackend: Send + Sync {
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Vec<Song>>>;
+
+    /// Looks up the song with the given ID. Returns `None` only if the backend knows that there is
+    /// no such song, and an error if it cannot tell.
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<Song>>>;
+
+    /// Returns where the client gets the audio of the song with the given ID from. Songs are
+    /// YouTube videos unless the backend knows better.
+    fn source(&self, _id: &str) -> SongSource {
+        SongSource::YouTube
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Song {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    #[serde(default)]
+    pub album: Option<String>,
+    /// Duration in seconds.
+    #[serde(default)]
+    pub duration: Option<u64>,
+    /// URL of the thumbnail. If unset, the thumbnail of the YouTube video with the ID is used.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub explicit: bool,
+    /// Whether the song is a livestream, which cannot be played.
+    #[serde(default)]
+    pub live: bool,
+}
+
+/// Reasons for rejecting a submitted song.
+pub enum Rejection {
+    NotFound,
+    Live,
+    TooLong { max: u64 },
+    Blocked,
+    Explicit,
+    Unavailable,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "The song does not exist or is not available."),
+            Self::Live => write!(f, "Livestreams cannot be played."),
+            Self::TooLong { max } => {
+                write!(f, "The song is too long, songs may be at most ")?;
+                write!(f, "{}:{:02} long.", max / 60, max % 60)
+            }
+            Self::Blocked => write!(f, "The host does not want this song to be played."),
+            Self::Explicit => write!(f, "The host does not want explicit songs to be played."),
+            Self::Unavailable => write!(
+                f,
+                "The song could not be checked right now. Please try again later."
+            ),
+        }
+    }
+}
+
+/// Creates the search backend selected in the configuration. Must be called before [`search`].
+pub async fn init() -> Result<()> {
+    let config = config::get();
+    let backend: Box<dyn SearchBackend> = match config.search_backend {
+        SearchBackendKind::YtMusic => {
+            log::info!("connecting to youtube music");
+            Box::new(YtMusicBackend::new().await?)
+        }
+        SearchBackendKind::Library => {
+            log::info!("indexing library at {}", config.library_path.display());
+            Box::new(LibraryBackend::new(&config.library_path)?)
+        }
+        SearchBackendKind::Fixture => {
+            log::info!(
+                "loading search fixtures from {}",
+                config.fixture_path.display()
+            );
+            Box::new(FixtureBackend::new(&config.fixture_path)?)
+        }
+    };
+    _ = BACKEND.set(backend);
+    Ok(())
+}
+
+pub async fn search(query: &str) -> Result<Vec<Song>> {
+    let backend = BACKEND.get().context("search backend is not initialized")?;
+    backend.search(query).await
+}
+
+/// Returns where the client gets the audio of the song with the given ID from.
+pub fn source(id: &str) -> SongSource {
+    match BACKEND.get() {
+        Some(backend) => backend.source(id),
+        None => SongSource::YouTube,
+    }
+}
+
+/// Checks that the song with the given ID exists, is not a livestream and is not longer than
+/// `max_duration` seconds (0 allows any duration). Songs that guests picked from recent search
+/// results are found in the cache, so the backend is only asked for unknown IDs.
+pub async fn validate(id: &str, max_duration: u64) -> Result<Song, Rejection> {
+    let backend = BACKEND.get().ok_or(Rejection::Unavailable)?;
+    validate_with(backend.as_ref(), id, max_duration).await
+}
+
+async fn validate_with(
+    backend: &dyn SearchBackend,
+    id: &str,
+    max_duration: u64,
+) -> Result<Song, Rejection> {
+    let cached = cache::get().find(id);
+    let song = match cached {
+        Some(song) => song,
+        None => match backend.lookup(id).await {
+            Ok(Some(song)) => song,
+            Ok(None) => return Err(Rejection::NotFound),
+            Err(e) => {
+                log::warn!("failed to look up {id}: {e:?}");
+                return Err(Rejection::Unavailable);
+            }
+        },
+    };
+
+    if song.live {
+        return Err(Rejection::Live);
+    }
+    let too_long = song
+        .duration
+        .is_some_and(|duration| duration > max_duration);
+    if max_duration > 0 && too_long {
+        return Err(Rejection::TooLong { max: max_duration });
+    }
+
+    Ok(song)
+}
+
+impl Song {
+    pub fn is_allowed_by(&self, filter: &SongFilter) -> bool {
+        filter.allows(&self.id, &self.title, &self.artist)
+    }
+}
+
+/// Returns whether all words of the query occur in the title or artist of the song, ignoring
+/// case. Used by the backends that search locally.
+pub fn matches(song: &Song, query: &str) -> bool {
+    let haystack = format!("{} {}", song.artist, song.title).to_lowercase();
+    query
+        .split_whitespace()
+        .all(|word| haystack.contains(&word.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn fixture() -> FixtureBackend {
+        FixtureBackend::new(&Path::new(env!("CARGO_MANIFEST_DIR")).join("fixture.json")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn searches_and_validates_fixture_songs() {
+        let backend = fixture();
+        let songs = backend.search("rick ASTLEY").await.unwrap();
+        let [song] = &songs[..] else {
+            panic!("expected exactly one song, found {}", songs.len());
+        };
+        assert_eq!(song.id, "dQw4w9WgXcQ");
+        assert_eq!(backend.source(&song.id), SongSource::YouTube);
+
+        let validated = validate_with(&backend, &song.id, 15 * 60).await;
+        assert!(validated.is_ok_and(|song| song.title == "Never Gonna Give You Up"));
+
+        assert!(backend.search("rick queen").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_live_and_long_songs() {
+        let backend = fixture();
+        let validated = validate_with(&backend, "aaaaaaaaaaa", 0).await;
+        assert!(matches!(validated, Err(Rejection::NotFound)));
+
+        let validated = validate_with(&backend, "jfKfPfyJRdk", 0).await;
+        assert!(matches!(validated, Err(Rejection::Live)));
+
+        let validated = validate_with(&backend, "ZNfRzH6Tp6U", 15 * 60).await;
+        assert!(matches!(validated, Err(Rejection::TooLong { max: 900 })));
+        assert!(validate_with(&backend, "ZNfRzH6Tp6U", 0).await.is_ok());
+    }
+}
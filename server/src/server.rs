@@ -1,77 +1,285 @@
-use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use anyhow::Result;
-use axum::extract::{Path, Query, WebSocketUpgrade};
-use axum::http::StatusCode;
-use axum::response::{Html, IntoResponse};
-use axum::routing::{any, get};
+use axum::extract::{ConnectInfo, Path, Query, WebSocketUpgrade};
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{any, get, post};
 use axum::{Form, Json, Router};
+use futures_util::stream;
 use serde::Deserialize;
+use shared::filter::SongFilter;
+use shared::protocol::{self, SessionConfig, SongStatus};
 use tokio::net::TcpListener;
 
-use crate::connections;
+use crate::cache;
+use crate::config;
+use crate::connections::{self, SubmitResult};
+use crate::guest;
+use crate::ratelimit;
+use crate::search::{self, Rejection};
 use crate::socket;
-use crate::ytapi;
+use crate::votes::VoteResult;
 
 pub async fn start() -> Result<()> {
+    search::init().await?;
+
     let app = Router::new()
         .route("/submit/{id}", get(get_submit).post(post_submit))
+        .route("/queue/{id}", get(get_queue))
+        .route("/queue/{id}/json", get(get_queue_json))
+        .route("/queue/{id}/events", get(get_queue_events))
+        .route("/queue/{id}/rejected", get(get_queue_rejected))
+        .route("/queue/{id}/upvote", post(post_upvote))
+        .route("/queue/{id}/skip", post(post_skip))
         .route("/ytapi/search", get(ytapi_search))
         .route("/ws", any(websocket))
         .fallback(not_found);
 
-    let port = match env::var("SCHMU_SERVER_PORT") {
-        Ok(port) => port.parse().unwrap(),
-        Err(_) => shared::consts::SERVER_PORT_SERVER,
-    };
-
-    let address = format!("0.0.0.0:{port}");
+    let address = format!("0.0.0.0:{}", config::get().port);
     log::info!("starting webserver on {address}");
     let listener = TcpListener::bind(&address).await?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
     axum::serve(listener, app).await?;
 
     Ok(())
 }
 
 const HTML_NOT_FOUND: &str = include_str!("pages/404.html");
+const HTML_QUEUE: &str = include_str!("pages/queue.html");
+const HTML_QUEUED: &str = include_str!("pages/queued.html");
+const HTML_RATE_LIMITED: &str = include_str!("pages/ratelimited.html");
+const HTML_REJECTED: &str = include_str!("pages/rejected.html");
 const HTML_SUBMIT: &str = include_str!("pages/submit.html");
 const HTML_SUCCESS: &str = include_str!("pages/success.html");
 
-async fn get_submit(Path(id): Path<String>) -> impl IntoResponse {
+async fn get_submit(Path(id): Path<String>, headers: HeaderMap) -> impl IntoResponse {
     log::info!("get /submit/{id}");
     if !connections::get().await.exists(&id) {
         return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
     }
-    Html(HTML_SUBMIT).into_response()
+    let (_, cookie) = guest::get_or_create(&headers);
+    (cookie, Html(HTML_SUBMIT)).into_response()
 }
 
 async fn post_submit(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Path(id): Path<String>,
+    headers: HeaderMap,
     Form(form): Form<SubmitPostForm>,
 ) -> impl IntoResponse {
     log::info!("post /submit/{id}?id={}", form.id);
-    if form.id.len() != 11 {
+    if !protocol::is_valid_song_id(&form.id) {
         return (StatusCode::BAD_REQUEST, "Invalid song ID").into_response();
     }
-    connections::get().await.submit(&id, &form.id).await;
-    Html(HTML_SUCCESS).into_response()
+    let ip = ratelimit::client_ip(addr, &headers);
+    if let Err(retry_after) = ratelimit::get().submit(ip, &id) {
+        log::warn!("rate limiting submission from {ip} to {id}");
+        return rate_limited(retry_after);
+    }
+    let (guest, cookie) = guest::get_or_create(&headers);
+
+    let Some((session_config, filter)) = session_settings(&id).await else {
+        return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
+    };
+    if filter.blocks_id(&form.id) {
+        log::info!("rejecting {} for {id}: blocked", form.id);
+        return rejected(Rejection::Blocked, cookie);
+    }
+    let validation = {
+        // looking up the song might hit the upstream service, just like a search
+        let Ok(_permit) = ratelimit::get().searches.acquire().await else {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Internal error").into_response();
+        };
+        search::validate(&form.id, session_config.max_duration).await
+    };
+    let validation = validation.and_then(|song| match song.is_allowed_by(&filter) {
+        true => Ok(song),
+        false => Err(Rejection::Blocked),
+    });
+    let validation = validation.and_then(|song| match session_config.hide_explicit {
+        true if song.explicit => Err(Rejection::Explicit),
+        _ => Ok(song),
+    });
+    if let Err(rejection) = validation {
+        log::info!("rejecting {} for {id}: {rejection}", form.id);
+        return rejected(rejection, cookie);
+    }
+
+    let source = search::source(&form.id);
+    let result = connections::get()
+        .await
+        .submit(&id, &form.id, source, &guest);
+    let response = match result {
+        SubmitResult::Delivered => Html(HTML_SUCCESS).into_response(),
+        SubmitResult::Queued => Html(HTML_QUEUED).into_response(),
+        SubmitResult::QueueFull => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many songs are waiting for the player to reconnect",
+        )
+            .into_response(),
+        SubmitResult::GuestLimitReached => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "You already have too many songs in the queue, wait until one of them was played",
+        )
+            .into_response(),
+        SubmitResult::InvalidSession => {
+            (StatusCode::BAD_REQUEST, "Invalid session").into_response()
+        }
+    };
+    (cookie, response).into_response()
+}
+
+async fn get_queue(Path(id): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    log::info!("get /queue/{id}");
+    if !connections::get().await.exists(&id) {
+        return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
+    }
+    let (_, cookie) = guest::get_or_create(&headers);
+    (cookie, Html(HTML_QUEUE)).into_response()
+}
+
+async fn get_queue_json(Path(id): Path<String>) -> impl IntoResponse {
+    log::info!("get /queue/{id}/json");
+    let Some(status) = connections::get().await.subscribe(&id) else {
+        return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
+    };
+    let status = status.borrow().clone();
+    Json(status).into_response()
+}
+
+async fn get_queue_events(Path(id): Path<String>) -> impl IntoResponse {
+    log::info!("get /queue/{id}/events");
+    let Some(receiver) = connections::get().await.subscribe(&id) else {
+        return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
+    };
+
+    // send the current status immediately, then every time it changes
+    let events = stream::unfold((receiver, true), |(mut receiver, first)| async move {
+        if !first && receiver.changed().await.is_err() {
+            return None;
+        }
+        let event = Event::default().json_data(&*receiver.borrow_and_update());
+        Some((event, (receiver, false)))
+    });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Returns the songs of the requesting guest that were recently rejected by the host.
+async fn get_queue_rejected(Path(id): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    log::info!("get /queue/{id}/rejected");
+    let Some(guest) = guest::from_headers(&headers) else {
+        return Json(Vec::<SongStatus>::new()).into_response();
+    };
+    match connections::get().await.rejected(&id, &guest) {
+        Some(rejected) => Json(rejected).into_response(),
+        None => (StatusCode::BAD_REQUEST, "Invalid session").into_response(),
+    }
+}
+
+async fn post_upvote(
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<UpvotePostForm>,
+) -> impl IntoResponse {
+    log::info!("post /queue/{id}/upvote?id={}", form.id);
+    let (guest, cookie) = guest::get_or_create(&headers);
+    let result = connections::get().await.upvote(&id, &form.id, &guest);
+    (cookie, vote_response(result)).into_response()
+}
+
+async fn post_skip(Path(id): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    log::info!("post /queue/{id}/skip");
+    let (guest, cookie) = guest::get_or_create(&headers);
+    let result = connections::get().await.vote_skip(&id, &guest);
+    (cookie, vote_response(result)).into_response()
+}
+
+fn vote_response(result: VoteResult) -> impl IntoResponse {
+    match result {
+        VoteResult::Counted | VoteResult::ThresholdReached => (StatusCode::OK, "Vote counted"),
+        VoteResult::AlreadyVoted => (StatusCode::CONFLICT, "You already voted"),
+        VoteResult::Disabled => (StatusCode::FORBIDDEN, "Voting is disabled"),
+        VoteResult::InvalidSong => (StatusCode::BAD_REQUEST, "Invalid song"),
+        VoteResult::Offline => (StatusCode::SERVICE_UNAVAILABLE, "The player is offline"),
+        VoteResult::InvalidSession => (StatusCode::BAD_REQUEST, "Invalid session"),
+    }
 }
 
 async fn not_found() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, Html(HTML_NOT_FOUND))
 }
 
-async fn ytapi_search(Query(query): Query<YtapiSearchQuery>) -> impl IntoResponse {
+async fn ytapi_search(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<YtapiSearchQuery>,
+) -> impl IntoResponse {
     log::info!("post /ytapi/search?query={}", query.query);
-    match ytapi::search(&query.query).await {
-        Ok(songs) => Json(songs).into_response(),
+    let Some((session_config, filter)) = session_settings(&query.session).await else {
+        return (StatusCode::BAD_REQUEST, "Invalid session").into_response();
+    };
+    let ip = ratelimit::client_ip(addr, &headers);
+    if let Err(retry_after) = ratelimit::get().search(ip, &query.session) {
+        log::warn!("rate limiting search from {ip} for {}", query.session);
+        return rate_limited(retry_after);
+    }
+
+    let filter = |songs: Vec<search::Song>| -> Vec<search::Song> {
+        songs
+            .into_iter()
+            .filter(|song| !(session_config.hide_explicit && song.explicit))
+            .filter(|song| song.is_allowed_by(&filter))
+            .collect()
+    };
+
+    let cached = cache::get().get(&query.query);
+    if let Some(songs) = cached {
+        return Json(filter(songs)).into_response();
+    }
+
+    // the permit is held until the search is done
+    let Ok(_permit) = ratelimit::get().searches.acquire().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Internal error").into_response();
+    };
+    match search::search(&query.query).await {
+        Ok(songs) => {
+            cache::get().insert(&query.query, songs.clone());
+            Json(filter(songs)).into_response()
+        }
         Err(e) => {
-            log::warn!("failed to search on youtube: {e:?}");
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(())).into_response()
+            log::warn!("failed to search: {e:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Search failed").into_response()
         }
     }
 }
 
+async fn session_settings(id: &str) -> Option<(SessionConfig, SongFilter)> {
+    let mut connections = connections::get().await;
+    Some((connections.config(id)?, connections.filter(id)?))
+}
+
+fn rejected(rejection: Rejection, cookie: HeaderMap) -> Response {
+    let html = HTML_REJECTED.replace("{{reason}}", &rejection.to_string());
+    (StatusCode::UNPROCESSABLE_ENTITY, cookie, Html(html)).into_response()
+}
+
+fn rate_limited(retry_after: Duration) -> Response {
+    let retry_after = retry_after.as_secs() + 1;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after.to_string())],
+        Html(HTML_RATE_LIMITED),
+    )
+        .into_response()
+}
+
 async fn websocket(ws: WebSocketUpgrade) -> impl IntoResponse {
     log::info!("websocket /ws");
     ws.on_upgrade(socket::handle)
@@ -82,7 +290,14 @@ struct SubmitPostForm {
     id: String,
 }
 
+#[derive(Deserialize)]
+struct UpvotePostForm {
+    id: String,
+}
+
 #[derive(Deserialize)]
 struct YtapiSearchQuery {
     query: String,
+    /// ID of the session the search is made for, used for rate limiting.
+    session: String,
 }
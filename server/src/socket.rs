@@ -2,8 +2,9 @@ use std::time::Duration;
 
 use anyhow::{bail, Result};
 use axum::extract::ws::{Message, WebSocket};
+use futures_util::stream::SplitSink;
 use futures_util::{select, FutureExt, SinkExt, StreamExt};
-use shared::misc::CallOnDrop;
+use shared::protocol::{self, ClientMessage, ServerMessage, PROTOCOL_VERSION};
 use tokio::time::sleep;
 
 use crate::connections;
@@ -17,30 +18,47 @@ pub async fn handle(socket: WebSocket) {
 async fn try_handle(socket: WebSocket) -> Result<()> {
     let (mut outgoing, mut incoming) = socket.split();
 
-    let id = match incoming.next().await {
-        Some(Ok(Message::Text(t))) if t == "hello" => None,
-        Some(Ok(Message::Text(t))) if t.starts_with("hello:") => {
-            let request_id = &t[6..];
-            if is_valid_id_request(request_id) {
-                bail!("invalid id request");
-            } else {
-                Some(request_id.to_owned())
-            }
-        }
+    let hello = match incoming.next().await {
+        Some(Ok(Message::Text(t))) => protocol::decode::<ClientMessage>(&t),
         Some(Ok(_)) => bail!("invalid hello message received after connect"),
         Some(Err(e)) => bail!("error after connect: {e}"),
         None => bail!("no hello message received after connect"),
     };
 
-    let (id, mut song_receiver) = connections::get().await.register(id);
-    let _unregister_guard = CallOnDrop::new(|| {
-        let id = id.clone();
-        tokio::spawn(async move { connections::get().await.unregister(&id) })
-    });
+    let (id, resume_token) = match hello {
+        Ok(ClientMessage::Hello {
+            version,
+            request_id,
+            resume_token,
+        }) => {
+            if version != PROTOCOL_VERSION {
+                reject_version(&mut outgoing, version).await?;
+                bail!("client uses unsupported protocol version {version}");
+            }
+            match request_id {
+                Some(request_id) if !is_valid_id_request(&request_id) => {
+                    bail!("invalid id request")
+                }
+                request_id => (request_id, resume_token),
+            }
+        }
+        Ok(_) => bail!("invalid hello message received after connect"),
+        // clients predating the versioned protocol send a plain text hello
+        Err(_) => {
+            reject_version(&mut outgoing, 0).await?;
+            bail!("client uses unversioned protocol");
+        }
+    };
+
+    // unregisters the connection when dropped
+    let mut registration = connections::get().await.register(id, resume_token);
+    let id = registration.id.clone();
     log::info!("assigned id {id}");
-    outgoing
-        .send(Message::Text(format!("hello:{id}").into()))
-        .await?;
+    let msg = ServerMessage::Hello {
+        id: id.clone(),
+        resume_token: registration.resume_token.clone(),
+    };
+    send(&mut outgoing, &msg).await?;
 
     loop {
         select! {
@@ -49,6 +67,30 @@ async fn try_handle(socket: WebSocket) -> Result<()> {
                     log::info!("closing connection to {id}");
                     break;
                 }
+                Some(Ok(Message::Text(t))) => match protocol::decode::<ClientMessage>(&t) {
+                    Ok(ClientMessage::Config(config)) => {
+                        connections::get()
+                            .await
+                            .update_config(&id, registration.generation, config);
+                    }
+                    Ok(ClientMessage::Filter(filter)) => {
+                        connections::get()
+                            .await
+                            .update_filter(&id, registration.generation, filter);
+                    }
+                    Ok(ClientMessage::Status(status)) => {
+                        connections::get()
+                            .await
+                            .update_status(&id, registration.generation, status);
+                    }
+                    Ok(ClientMessage::Rejected(song)) => {
+                        connections::get()
+                            .await
+                            .reject(&id, registration.generation, song);
+                    }
+                    Ok(ClientMessage::Hello { .. }) => log::warn!("{id} sent hello twice"),
+                    Err(e) => log::warn!("failed to parse message from {id}: {e}"),
+                },
                 Some(Ok(_)) => (),
                 Some(Err(e)) => Err(e)?,
                 None => {
@@ -57,11 +99,17 @@ async fn try_handle(socket: WebSocket) -> Result<()> {
                 },
             },
 
-            song = song_receiver.recv().fuse() => if let Some(song) = song {
-                log::info!("pushing {song} to {id}");
-                outgoing
-                    .send(Message::Text(format!("push:{song}").into()))
-                    .await?;
+            msg = registration.receiver.recv().fuse() => match msg {
+                Some(msg) => {
+                    if let ServerMessage::Push { ref song_id, .. } = msg {
+                        log::info!("pushing {song_id} to {id}");
+                    }
+                    send(&mut outgoing, &msg).await?;
+                }
+                None => {
+                    log::info!("connection to {id} was taken over by a reconnect, closing");
+                    break;
+                }
             },
 
             _ = sleep(Duration::from_secs(1)).fuse() => {
@@ -73,7 +121,23 @@ async fn try_handle(socket: WebSocket) -> Result<()> {
     Ok(())
 }
 
+async fn send(outgoing: &mut SplitSink<WebSocket, Message>, msg: &ServerMessage) -> Result<()> {
+    let msg = protocol::encode(msg);
+    outgoing.send(Message::Text(msg.into())).await?;
+    Ok(())
+}
+
+async fn reject_version(outgoing: &mut SplitSink<WebSocket, Message>, version: u32) -> Result<()> {
+    log::info!("rejecting client with protocol version {version}");
+    let msg = ServerMessage::UnsupportedVersion {
+        version: PROTOCOL_VERSION,
+    };
+    send(outgoing, &msg).await?;
+    outgoing.send(Message::Close(None)).await?;
+    Ok(())
+}
+
 fn is_valid_id_request(id: &str) -> bool {
     let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
-    id.len() >= 4 && id.chars().any(|c| !valid_char(c))
+    id.len() >= 4 && id.chars().all(valid_char)
 }
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+use shared::protocol::{PlayerStatus, SongStatus};
+
+/// Songs submitted by the guests of a session that have not been played yet. Used to limit the
+/// number of pending songs per guest, and to tell guests which of their songs were rejected by
+/// the host.
+#[derive(Default)]
+pub struct Submissions {
+    submissions: Vec<Submission>,
+    rejected: Vec<Rejected>,
+}
+
+struct Submission {
+    song_id: String,
+    guest: String,
+    submitted_at: Instant,
+    /// Whether the song has already shown up in the queue of the player.
+    seen: bool,
+    /// When the song was played or removed from the queue of the player.
+    removed_at: Option<Instant>,
+}
+
+struct Rejected {
+    song: SongStatus,
+    guest: String,
+    rejected_at: Instant,
+}
+
+impl Submissions {
+    /// Time after which a submission that never showed up in the queue of the player is
+    /// forgotten, e.g. because the player failed to download it.
+    const UNSEEN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+    /// Time after which a rejected song is no longer shown to its guest.
+    const REJECTED_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+    /// Time for which a submission is kept after it left the queue of the player, because the
+    /// player may report that it rejected the song only after it sent a status without it.
+    const REMOVED_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn add(&mut self, song_id: &str, guest: &str) {
+        if self.pending().any(|s| s.song_id == song_id) {
+            return;
+        }
+        self.submissions.retain(|s| s.song_id != song_id);
+
+        self.submissions.push(Submission {
+            song_id: song_id.to_owned(),
+            guest: guest.to_owned(),
+            submitted_at: Instant::now(),
+            seen: false,
+            removed_at: None,
+        });
+    }
+
+    /// Returns the number of songs submitted by the guest that have not been played yet.
+    pub fn count(&self, guest: &str) -> usize {
+        self.pending().filter(|s| s.guest == guest).count()
+    }
+
+    fn pending(&self) -> impl Iterator<Item = &Submission> {
+        self.submissions.iter().filter(|s| s.removed_at.is_none())
+    }
+
+    /// Forgets all submissions that were played or removed from the queue of the player a while
+    /// ago.
+    pub fn retain(&mut self, status: &PlayerStatus) {
+        self.submissions.retain_mut(|submission| {
+            let queued = status
+                .queue
+                .iter()
+                .chain(&status.pending_approval)
+                .any(|song| song.id == submission.song_id);
+            if queued {
+                submission.seen = true;
+                submission.removed_at = None;
+            } else if submission.seen && submission.removed_at.is_none() {
+                submission.removed_at = Some(Instant::now());
+            }
+            match submission.removed_at {
+                Some(removed_at) => removed_at.elapsed() < Self::REMOVED_TIMEOUT,
+                None => queued || submission.submitted_at.elapsed() < Self::UNSEEN_TIMEOUT,
+            }
+        });
+    }
+
+    /// Marks the submission of the song as rejected by the host.
+    pub fn reject(&mut self, song: SongStatus) {
+        let Some(index) = self.submissions.iter().position(|s| s.song_id == song.id) else {
+            return;
+        };
+        let submission = self.submissions.remove(index);
+        self.rejected.push(Rejected {
+            song,
+            guest: submission.guest,
+            rejected_at: Instant::now(),
+        });
+    }
+
+    /// Returns the songs of the guest that were recently rejected by the host.
+    pub fn rejected(&mut self, guest: &str) -> Vec<SongStatus> {
+        self.rejected
+            .retain(|rejected| rejected.rejected_at.elapsed() < Self::REJECTED_TIMEOUT);
+        self.rejected
+            .iter()
+            .filter(|rejected| rejected.guest == guest)
+            .map(|rejected| rejected.song.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str) -> SongStatus {
+        SongStatus {
+            id: id.to_owned(),
+            title: id.to_owned(),
+            artist: "artist".to_owned(),
+            downloaded: false,
+            thumbnail: None,
+        }
+    }
+
+    fn status(queue: &[&str]) -> PlayerStatus {
+        PlayerStatus {
+            queue: queue.iter().map(|id| song(id)).collect(),
+            ..PlayerStatus::default()
+        }
+    }
+
+    #[test]
+    fn played_songs_no_longer_count() {
+        let mut submissions = Submissions::default();
+        submissions.add("a", "x");
+        submissions.add("b", "x");
+        submissions.retain(&status(&["a", "b"]));
+        assert_eq!(submissions.count("x"), 2);
+
+        submissions.retain(&status(&["b"]));
+        assert_eq!(submissions.count("x"), 1);
+
+        // the song may be submitted again once it was played
+        submissions.add("a", "y");
+        assert_eq!(submissions.count("y"), 1);
+    }
+
+    #[test]
+    fn rejections_after_the_song_left_the_queue_reach_the_guest() {
+        let mut submissions = Submissions::default();
+        submissions.add("a", "x");
+        submissions.retain(&status(&["a"]));
+        submissions.retain(&status(&[]));
+
+        submissions.reject(song("a"));
+        assert_eq!(submissions.rejected("x"), [song("a")]);
+        assert!(submissions.rejected("y").is_empty());
+    }
+
+    #[test]
+    fn removed_songs_are_forgotten() {
+        let mut submissions = Submissions::default();
+        submissions.add("a", "x");
+        submissions.retain(&status(&["a"]));
+        submissions.retain(&status(&[]));
+        submissions.submissions[0].removed_at = Some(Instant::now() - Submissions::REMOVED_TIMEOUT);
+        submissions.retain(&status(&[]));
+
+        submissions.reject(song("a"));
+        assert!(submissions.rejected("x").is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::{HashMap, HashSet};
+
+use shared::protocol::PlayerStatus;
+
+/// Votes cast by the guests of a session. Guests are identified by their guest token, so that
+/// every guest can only vote once per song.
+#[derive(Default)]
+pub struct Votes {
+    upvotes: HashMap<String, HashSet<String>>,
+    skip_song: Option<String>,
+    skips: HashSet<String>,
+}
+
+pub enum VoteResult {
+    Counted,
+    ThresholdReached,
+    AlreadyVoted,
+    Disabled,
+    InvalidSong,
+    Offline,
+    InvalidSession,
+}
+
+impl Votes {
+    pub fn upvote(&mut self, song_id: &str, guest: &str, threshold: u32) -> VoteResult {
+        if threshold == 0 {
+            return VoteResult::Disabled;
+        }
+
+        let voters = self.upvotes.entry(song_id.to_owned()).or_default();
+        if !voters.insert(guest.to_owned()) {
+            return VoteResult::AlreadyVoted;
+        }
+
+        if voters.len() >= threshold as usize {
+            self.upvotes.remove(song_id);
+            VoteResult::ThresholdReached
+        } else {
+            VoteResult::Counted
+        }
+    }
+
+    pub fn skip(&mut self, song_id: &str, guest: &str, threshold: u32) -> VoteResult {
+        if threshold == 0 {
+            return VoteResult::Disabled;
+        }
+
+        if self.skip_song.as_deref() != Some(song_id) {
+            self.skip_song = Some(song_id.to_owned());
+            self.skips.clear();
+        }
+
+        if !self.skips.insert(guest.to_owned()) {
+            return VoteResult::AlreadyVoted;
+        }
+
+        if self.skips.len() >= threshold as usize {
+            self.skips.clear();
+            VoteResult::ThresholdReached
+        } else {
+            VoteResult::Counted
+        }
+    }
+
+    /// Forgets all votes for songs that are no longer queued or playing.
+    pub fn retain(&mut self, status: &PlayerStatus) {
+        self.upvotes
+            .retain(|id, _| status.queue.iter().any(|song| &song.id == id));
+
+        let playing = status
+            .playing
+            .as_ref()
+            .map(|playing| playing.song.id.as_str());
+        if self.skip_song.as_deref() != playing {
+            self.skip_song = None;
+            self.skips.clear();
+        }
+    }
+
+    pub fn upvote_counts(&self) -> HashMap<String, usize> {
+        self.upvotes
+            .iter()
+            .map(|(id, voters)| (id.clone(), voters.len()))
+            .collect()
+    }
+
+    pub fn skip_count(&self) -> usize {
+        self.skips.len()
+    }
+}
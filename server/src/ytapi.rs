@@ -1,33 +1,144 @@
 use std::env;
+use std::process::Stdio;
+use std::time::Duration;
 
-use anyhow::Result;
-use serde::Serialize;
-use ytmapi_rs::common::YoutubeID;
+use anyhow::{bail, Context, Result};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::time;
+use ytmapi_rs::auth::BrowserToken;
+use ytmapi_rs::common::{Explicit, YoutubeID};
 use ytmapi_rs::YtMusic;
 
-pub async fn search(query: &str) -> Result<Vec<Song>> {
-    let ytm = match env::var("SCHMU_SERVER_YTAPI_COOKIE") {
-        Ok(cookie) => YtMusic::from_cookie(cookie).await?,
-        Err(_) => YtMusic::from_cookie_file("./cookie.txt").await?,
-    };
+use crate::search::{SearchBackend, Song};
 
-    let songs = ytm.search_songs(query).await?;
+/// Searches songs on YouTube Music. A single client is shared by all searches.
+pub struct YtMusicBackend {
+    ytm: YtMusic<BrowserToken>,
+}
+
+impl YtMusicBackend {
+    const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub async fn new() -> Result<Self> {
+        let ytm = match env::var("SCHMU_SERVER_YTAPI_COOKIE") {
+            Ok(cookie) => YtMusic::from_cookie(cookie).await?,
+            Err(_) => YtMusic::from_cookie_file("./cookie.txt").await?,
+        };
+        Ok(Self { ytm })
+    }
+
+    /// YouTube Music has no way to fetch a single song by its ID, so the video is looked up with
+    /// yt-dlp instead, which also tells whether it is a livestream.
+    async fn lookup_song(&self, id: &str) -> Result<Option<Song>> {
+        let mut command = Command::new("yt-dlp");
+        command
+            .args([
+                "--dump-json",
+                "--skip-download",
+                "--no-playlist",
+                "--no-warnings",
+            ])
+            .arg(format!("https://www.youtube.com/watch?v={id}"))
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        let output = time::timeout(Self::LOOKUP_TIMEOUT, command.output())
+            .await
+            .context("yt-dlp timed out")?
+            .context("failed to run yt-dlp")?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            if VIDEO_MISSING_ERRORS.iter().any(|msg| error.contains(msg)) {
+                return Ok(None);
+            }
+            bail!("yt-dlp failed: {}", error.trim());
+        }
+
+        let video = serde_json::from_slice::<VideoInfo>(&output.stdout)?;
+        Ok(Some(Song {
+            id: video.id,
+            title: video.track.unwrap_or(video.title),
+            artist: match video.artists {
+                Some(artists) if !artists.is_empty() => artists.join(", "),
+                _ => video.uploader.unwrap_or_default(),
+            },
+            album: video.album,
+            duration: video.duration.map(|secs| secs.round() as u64),
+            live: matches!(
+                video.live_status.as_deref(),
+                Some("is_live" | "is_upcoming")
+            ),
+            thumbnail: video.thumbnail,
+            // only YouTube Music knows whether a song is explicit
+            explicit: false,
+        }))
+    }
+
+    async fn search_songs(&self, query: &str) -> Result<Vec<Song>> {
+        let songs = self.ytm.search_songs(query).await?;
+
+        let result = songs
+            .into_iter()
+            .map(|song| Song {
+                id: song.video_id.get_raw().to_owned(),
+                title: song.title,
+                artist: song.artist,
+                album: song.album.map(|album| album.name),
+                duration: parse_duration(&song.duration),
+                // searching for songs only finds music tracks, which are never livestreams
+                live: false,
+                thumbnail: song
+                    .thumbnails
+                    .into_iter()
+                    .max_by_key(|thumbnail| thumbnail.width)
+                    .map(|thumbnail| thumbnail.url),
+                explicit: matches!(song.explicit, Explicit::IsExplicit),
+            })
+            .collect();
+
+        Ok(result)
+    }
+}
+
+impl SearchBackend for YtMusicBackend {
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Vec<Song>>> {
+        self.search_songs(query).boxed()
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<Song>>> {
+        self.lookup_song(id).boxed()
+    }
+}
 
-    let result = songs
-        .into_iter()
-        .map(|song| Song {
-            id: song.video_id.get_raw().to_owned(),
-            title: song.title,
-            artist: song.artist,
-        })
-        .collect();
+/// Parts of the errors yt-dlp reports for videos that do not exist or that nobody may watch.
+const VIDEO_MISSING_ERRORS: &[&str] = &[
+    "Video unavailable",
+    "Private video",
+    "This video has been removed",
+    "This video is not available",
+    "Incomplete YouTube ID",
+];
 
-    Ok(result)
+/// The fields of the video info printed by yt-dlp that describe the song.
+#[derive(Deserialize)]
+struct VideoInfo {
+    id: String,
+    title: String,
+    track: Option<String>,
+    artists: Option<Vec<String>>,
+    uploader: Option<String>,
+    album: Option<String>,
+    duration: Option<f64>,
+    live_status: Option<String>,
+    thumbnail: Option<String>,
 }
 
-#[derive(Serialize)]
-pub struct Song {
-    pub id: String,
-    pub title: String,
-    pub artist: String,
+/// Parses a duration like `3:45` or `1:02:03` into seconds.
+fn parse_duration(duration: &str) -> Option<u64> {
+    duration
+        .split(':')
+        .try_fold(0, |secs, part| Some(secs * 60 + part.parse::<u64>().ok()?))
 }
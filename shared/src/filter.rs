@@ -0,0 +1,170 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Songs that may or may not be played, chosen by the host. A song is rejected if it matches any
+/// blocked rule, or if there are allowed rules and it matches none of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SongFilter {
+    pub blocked: Vec<Rule>,
+    pub allowed: Vec<Rule>,
+}
+
+impl SongFilter {
+    /// Returns whether the song is blocked by its ID alone. Used before anything else is known
+    /// about the song.
+    pub fn blocks_id(&self, id: &str) -> bool {
+        self.blocked
+            .iter()
+            .any(|rule| matches!(rule, Rule::Id(blocked) if blocked == id))
+    }
+
+    pub fn allows(&self, id: &str, title: &str, artist: &str) -> bool {
+        let matches = |rule: &Rule| rule.matches(id, title, artist);
+        !self.blocked.iter().any(matches)
+            && (self.allowed.is_empty() || self.allowed.iter().any(matches))
+    }
+}
+
+/// A rule matching songs. In files, rules are written one per line as `id:<video id>`,
+/// `artist:<substring>` or `title:<pattern>`, where `*` in a title pattern matches any text.
+/// Artists and titles are matched ignoring case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Rule {
+    Id(String),
+    Artist(String),
+    Title(String),
+}
+
+impl Rule {
+    pub fn matches(&self, id: &str, title: &str, artist: &str) -> bool {
+        match self {
+            Self::Id(rule) => rule == id,
+            Self::Artist(rule) => artist.to_lowercase().contains(&rule.to_lowercase()),
+            Self::Title(rule) => glob_matches(&rule.to_lowercase(), &title.to_lowercase()),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("id", id)) => Ok(Self::Id(id.trim().to_owned())),
+            Some(("artist", artist)) => Ok(Self::Artist(artist.trim().to_owned())),
+            Some(("title", title)) => Ok(Self::Title(title.trim().to_owned())),
+            _ => Err(format!("invalid rule {s:?}")),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "id:{id}"),
+            Self::Artist(artist) => write!(f, "artist:{artist}"),
+            Self::Title(title) => write!(f, "title:{title}"),
+        }
+    }
+}
+
+fn glob_matches(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcard in the pattern
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(blocked: &[&str], allowed: &[&str]) -> SongFilter {
+        let parse = |rules: &[&str]| rules.iter().map(|rule| rule.parse().unwrap()).collect();
+        SongFilter {
+            blocked: parse(blocked),
+            allowed: parse(allowed),
+        }
+    }
+
+    #[test]
+    fn glob_without_wildcard_matches_whole_string() {
+        assert!(glob_matches("song", "song"));
+        assert!(!glob_matches("song", "songs"));
+        assert!(!glob_matches("song", "a song"));
+    }
+
+    #[test]
+    fn glob_with_wildcards() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*remix*", "song (remix)"));
+        assert!(glob_matches("live at *", "live at wembley"));
+        assert!(glob_matches("* (live)", "song (live)"));
+        assert!(glob_matches("a*b*c", "abc"));
+        assert!(glob_matches("a*b*c", "a123b456c"));
+        assert!(!glob_matches("a*b*c", "acb"));
+        assert!(!glob_matches("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn rules_ignore_case_except_for_ids() {
+        let rule = "artist:Rick".parse::<Rule>().unwrap();
+        assert!(rule.matches("dQw4w9WgXcQ", "Never Gonna Give You Up", "rick astley"));
+
+        let rule = "title:*GONNA*".parse::<Rule>().unwrap();
+        assert!(rule.matches("dQw4w9WgXcQ", "Never Gonna Give You Up", "Rick Astley"));
+
+        let rule = "id:dqw4w9wgxcq".parse::<Rule>().unwrap();
+        assert!(!rule.matches("dQw4w9WgXcQ", "Never Gonna Give You Up", "Rick Astley"));
+    }
+
+    #[test]
+    fn rules_round_trip_through_strings() {
+        for s in ["id:dQw4w9WgXcQ", "artist:Rick Astley", "title:* (live)"] {
+            assert_eq!(s.parse::<Rule>().unwrap().to_string(), s);
+        }
+        assert!("album:foo".parse::<Rule>().is_err());
+        assert!("foo".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn blocked_rules_win_over_allowed_rules() {
+        let filter = filter(&["title:*live*"], &["artist:queen"]);
+        assert!(filter.allows("aaaaaaaaaaa", "Bohemian Rhapsody", "Queen"));
+        assert!(!filter.allows("aaaaaaaaaaa", "Bohemian Rhapsody (Live Aid)", "Queen"));
+        assert!(!filter.allows("aaaaaaaaaaa", "Under Pressure", "David Bowie"));
+    }
+
+    #[test]
+    fn empty_filter_allows_everything() {
+        let filter = SongFilter::default();
+        assert!(filter.allows("aaaaaaaaaaa", "title", "artist"));
+        assert!(!filter.blocks_id("aaaaaaaaaaa"));
+    }
+
+    #[test]
+    fn only_id_rules_block_ids() {
+        let filter = filter(&["id:aaaaaaaaaaa", "artist:aaaaaaaaaaa"], &[]);
+        assert!(filter.blocks_id("aaaaaaaaaaa"));
+        assert!(!filter.blocks_id("bbbbbbbbbbb"));
+    }
+}
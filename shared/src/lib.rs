@@ -1,3 +1,5 @@
 pub mod consts;
+pub mod filter;
 pub mod logger;
 pub mod misc;
+pub mod protocol;
@@ -15,3 +15,21 @@ impl<T, F: FnOnce() -> T> Drop for CallOnDrop<T, F> {
         }
     }
 }
+
+/// Derives an ID in the format of a YouTube video ID from an arbitrary key, so that songs from
+/// other sources pass the same validation. The ID is stable across builds.
+pub fn hash_id(key: &str) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    // 64-bit fnv-1a, which is stable across builds unlike the hasher of the standard library
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    // 11 characters of 6 bits each cover all 64 bits of the hash
+    (0..11)
+        .map(|i| CHARSET[((hash >> (i * 6)) & 0x3f) as usize] as char)
+        .collect()
+}
@@ -0,0 +1,178 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::filter::SongFilter;
+
+/// Version of the WebSocket protocol spoken between client and server. Must be incremented
+/// whenever a message is changed in a way that older peers cannot understand.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Messages sent from the client to the server.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// First message after connecting. When reconnecting, `request_id` and `resume_token` should
+    /// be set to the values received in the previous [`ServerMessage::Hello`] in order to reclaim
+    /// the ID.
+    Hello {
+        version: u32,
+        request_id: Option<String>,
+        resume_token: Option<String>,
+    },
+    /// Settings of the session, sent after [`ClientMessage::Hello`].
+    Config(SessionConfig),
+    /// Current state of the player, sent whenever it changes.
+    Status(PlayerStatus),
+    /// Songs the host does or does not want to be played, sent after [`ClientMessage::Hello`] and
+    /// whenever it changes.
+    Filter(SongFilter),
+    /// The host rejected a song suggestion in moderation mode.
+    Rejected(SongStatus),
+}
+
+/// Messages sent from the server to the client.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Response to a successful [`ClientMessage::Hello`] containing the assigned ID and a token
+    /// that allows the client to reclaim the ID after a disconnect.
+    Hello { id: String, resume_token: String },
+    /// Response to a [`ClientMessage::Hello`] with a protocol version the server does not speak.
+    /// The server closes the connection after sending this.
+    UnsupportedVersion { version: u32 },
+    /// A song was submitted by a listener. `submitter` identifies the listener, so that the
+    /// client can interleave the songs of different listeners.
+    Push {
+        song_id: String,
+        source: SongSource,
+        submitter: Option<String>,
+    },
+    /// Enough listeners voted for a queued song to move it up by one position.
+    MoveUp { song_id: String },
+    /// Enough listeners voted to skip the currently playing song.
+    Skip { song_id: String },
+}
+
+/// Where the client gets the audio of a submitted song from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum SongSource {
+    /// A YouTube video whose ID is the song ID.
+    #[serde(rename = "youtube")]
+    YouTube,
+    /// A file of the music library searched by the server, given as a path relative to the
+    /// library directory. The song ID is derived from the path with [`crate::misc::hash_id`], the
+    /// client plays the file from its own copy of the library.
+    Library(String),
+}
+
+/// Settings of the session that are chosen by the client and enforced by the server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionConfig {
+    /// Number of votes needed to move a queued song up. 0 disables upvoting.
+    pub upvote_threshold: u32,
+    /// Number of votes needed to skip the current song. 0 disables skipping.
+    pub skip_threshold: u32,
+    /// Maximum number of songs a listener may have in the queue. 0 disables the limit.
+    pub max_songs_per_guest: u32,
+    /// Whether songs marked as explicit are hidden from the search results.
+    pub hide_explicit: bool,
+    /// Maximum duration of submitted songs in seconds. 0 disables the limit.
+    pub max_duration: u64,
+}
+
+/// Snapshot of the player state that is shown to listeners.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    pub playing: Option<PlayingStatus>,
+    pub queue: Vec<SongStatus>,
+    /// Song suggestions waiting for the host to approve them in moderation mode.
+    #[serde(default)]
+    pub pending_approval: Vec<SongStatus>,
+    /// Number of songs in the fallback queue.
+    pub fallback_queue_len: usize,
+    /// The most recently played songs, latest first.
+    #[serde(default)]
+    pub recently_played: Vec<PlayedStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayingStatus {
+    pub song: SongStatus,
+    /// Elapsed time in seconds.
+    pub elapsed: u64,
+    /// Total time in seconds.
+    pub total: u64,
+    pub is_fallback: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayedStatus {
+    pub song: SongStatus,
+    /// Time at which the song started playing as a UNIX timestamp in seconds.
+    pub played_at: u64,
+    pub is_fallback: bool,
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongStatus {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub downloaded: bool,
+    /// URL of the thumbnail, unset if the song has none that guests can load.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+}
+
+pub fn encode<T: Serialize>(msg: &T) -> String {
+    serde_json::to_string(msg).expect("protocol messages are always serializable")
+}
+
+pub fn decode<T: DeserializeOwned>(s: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+pub fn is_valid_song_id(id: &str) -> bool {
+    id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_carries_the_song_source() {
+        let msg = ServerMessage::Push {
+            song_id: "dQw4w9WgXcQ".to_owned(),
+            source: SongSource::YouTube,
+            submitter: None,
+        };
+        assert_eq!(
+            encode(&msg),
+            concat!(
+                r#"{"type":"push","song_id":"dQw4w9WgXcQ","#,
+                r#""source":{"kind":"youtube"},"submitter":null}"#
+            )
+        );
+
+        let msg = r#"{"type":"push","song_id":"aaaaaaaaaaa",
+            "source":{"kind":"library","path":"Artist/Song.mp3"},"submitter":"guest"}"#;
+        let Ok(ServerMessage::Push { source, .. }) = decode(msg) else {
+            panic!("failed to decode {msg}");
+        };
+        assert_eq!(source, SongSource::Library("Artist/Song.mp3".to_owned()));
+    }
+
+    #[test]
+    fn song_ids_have_the_format_of_video_ids() {
+        assert!(is_valid_song_id("dQw4w9WgXcQ"));
+        assert!(is_valid_song_id("a-b_c123456"));
+        assert!(!is_valid_song_id("dQw4w9WgXc"));
+        assert!(!is_valid_song_id("dQw4w9WgXc/"));
+    }
+}